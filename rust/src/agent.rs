@@ -0,0 +1,132 @@
+use rand::Rng;
+
+use board::{Direction, GameRng, seeded_rng};
+use game::GridGame;
+use heuristic::{Heuristic, RowHeuristic};
+use search::{search_grid_cached, GridSearchCache, SearchConfig, SearchResult, SearchStats};
+
+// The names of the agents agent_by_name can build, the default first
+pub const AGENT_NAMES: [&str; 2] = ["expectimax", "random"];
+
+// A player of the game. Agents are handed the whole game on each turn and may keep whatever state they like
+// between moves; the hooks let them set up and tear down that state around each game.
+// Any board size can be played; Agent is the player of the standard 4x4 game.
+pub trait Agent<const W: usize = 4, const H: usize = 4> {
+    // Returns the name of this agent, as given to agent_by_name
+    fn name(&self) -> &'static str;
+
+    // Returns the move to play next in the given game, or None to resign
+    fn choose_move(&mut self, game: &GridGame<W, H>) -> Option<Direction>;
+
+    // Called with the new game before the first move of each game
+    fn new_game(&mut self, _game: &GridGame<W, H>) {}
+
+    // Called with the finished game after its last move
+    fn end_game(&mut self, _game: &GridGame<W, H>) {}
+
+    // Returns what this agent's searches have done since the game began, added up over its moves
+    fn search_stats(&self) -> SearchStats {
+        SearchStats::default()
+    }
+
+    // Returns the result of the search behind the last move chosen, if the agent searches
+    fn last_search(&self) -> Option<SearchResult> {
+        None
+    }
+}
+
+// Builds the agent with the given name, or returns None if there is no such agent. Searching agents use the
+// given settings.
+pub fn agent_by_name<const W: usize, const H: usize>(name: &str, config: &SearchConfig) -> Option<Box<dyn Agent<W, H> + Send>> {
+    match name {
+        "expectimax" => Some(Box::new(ExpectimaxAgent::new(config.clone(), RowHeuristic::default()))),
+        "random"     => Some(Box::new(RandomAgent::new())),
+        _            => None,
+    }
+}
+
+// Plays the move the expectimax search rates best under its heuristic. Keeps its search cache for the whole of a
+// game, so positions searched for one move can be reused for the next.
+pub struct ExpectimaxAgent<E: Heuristic = RowHeuristic, const W: usize = 4, const H: usize = 4> {
+    config: SearchConfig,
+    heuristic: E,
+    cache: GridSearchCache<W, H>,
+    stats: SearchStats,  // What the searches have done this game
+    last: Option<SearchResult>,
+}
+
+impl<E: Heuristic, const W: usize, const H: usize> ExpectimaxAgent<E, W, H> {
+    pub fn new(config: SearchConfig, heuristic: E) -> ExpectimaxAgent<E, W, H> {
+        ExpectimaxAgent { config, heuristic, cache: GridSearchCache::new(), stats: SearchStats::default(), last: None }
+    }
+
+    // Returns the settings this agent searches with
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+}
+
+impl<const W: usize, const H: usize, E: Heuristic> Agent<W, H> for ExpectimaxAgent<E, W, H> {
+    fn name(&self) -> &'static str {
+        "expectimax"
+    }
+
+    fn choose_move(&mut self, game: &GridGame<W, H>) -> Option<Direction> {
+        let result = search_grid_cached(game.grid(), &self.config, &self.heuristic, &mut self.cache);
+        self.stats.add(result.stats);
+        self.last = Some(result);
+        result.best
+    }
+
+    fn new_game(&mut self, _game: &GridGame<W, H>) {
+        self.cache = GridSearchCache::new();
+        self.stats = SearchStats::default();
+        self.last = None;
+    }
+
+    fn search_stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn last_search(&self) -> Option<SearchResult> {
+        self.last
+    }
+}
+
+// Plays a random legal move, as a baseline for the other agents. Reseeded from the game's seed at the start of
+// each game, so its games are as repeatable as the spawns.
+pub struct RandomAgent {
+    rng: GameRng,
+}
+
+impl RandomAgent {
+    pub fn new() -> RandomAgent {
+        RandomAgent { rng: seeded_rng(0) }
+    }
+}
+
+impl Default for RandomAgent {
+    fn default() -> RandomAgent {
+        RandomAgent::new()
+    }
+}
+
+impl<const W: usize, const H: usize> Agent<W, H> for RandomAgent {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn choose_move(&mut self, game: &GridGame<W, H>) -> Option<Direction> {
+        let legal = game.legal_moves();
+        let moves: Vec<Direction> = Direction::ALL.iter().cloned().filter(|dir| legal & dir.mask() != 0).collect();
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[self.rng.gen_range(0, moves.len())])
+    }
+
+    fn new_game(&mut self, game: &GridGame<W, H>) {
+        // Flip the seed so the moves are not drawn from the same stream as the game's spawns
+        self.rng = seeded_rng(!game.seed());
+    }
+}
@@ -1,77 +1,198 @@
-use super::rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng, XorShiftRng};
 use std::cmp::max;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
-use super::ROW_LEFT_TABLE;
-use super::ROW_RIGHT_TABLE;
-use super::COL_UP_TABLE;
-use super::COL_DOWN_TABLE;
+use scoring::score_board;
+
+use generate_tables::tables;
 
 use super::COL_MASK;
 use super::ROW_MASK;
 
-// Return the result of the specified move on the given board.
-// mv: 0 -> up
-//     1 -> down
-//     2 -> right
-//     3 -> left
-// Any other value of mv will return a 0 board.
-pub fn execute_move(mv: u8, board: u64) -> u64 {
-    unsafe{
-        match mv {
-            0 => execute_move_0(board),
-            1 => execute_move_1(board),
-            2 => execute_move_2(board),
-            3 => execute_move_3(board),
-            _ => {println!("INVALID_MOVE"); 0}
+// The four ways the tiles can be swiped. Up is towards the first row, left is towards the first column.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    // Every direction, in the order used for move indices and move masks
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    // Returns the index of this direction within Direction::ALL
+    pub fn index(self) -> usize {
+        match self {
+            Direction::Up    => 0,
+            Direction::Down  => 1,
+            Direction::Left  => 2,
+            Direction::Right => 3,
+        }
+    }
+
+    // Returns the bit representing this direction in a move mask, as returned by legal_moves
+    pub fn mask(self) -> u8 {
+        1 << self.index()
+    }
+
+    // Returns the direction with the given index within Direction::ALL, if there is one
+    pub fn from_index(index: usize) -> Option<Direction> {
+        Direction::ALL.get(index).cloned()
+    }
+
+    // Returns the single letter standing for this direction in a written list of moves
+    pub fn letter(self) -> char {
+        match self {
+            Direction::Up    => 'u',
+            Direction::Down  => 'd',
+            Direction::Left  => 'l',
+            Direction::Right => 'r',
         }
     }
 }
 
+// Reads a direction from its name or its letter, in either case
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Direction, String> {
+        match s.to_lowercase().as_str() {
+            "up"    | "u" => Ok(Direction::Up),
+            "down"  | "d" => Ok(Direction::Down),
+            "left"  | "l" => Ok(Direction::Left),
+            "right" | "r" => Ok(Direction::Right),
+            _             => Err(format!("unknown direction '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Direction::Up    => "up",
+            Direction::Down  => "down",
+            Direction::Left  => "left",
+            Direction::Right => "right",
+        };
+        f.write_str(name)
+    }
+}
+
+// The reasons a move can be refused
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    // The move would not change the board, so it is not a legal move
+    NoChange,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveError::NoChange => f.write_str("move does not change the board"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+// Return the result of the specified move on the given board. A move which changes nothing returns the
+// board unchanged; use Board::make_move to have that reported as an error.
+pub fn execute_move(dir: Direction, board: u64) -> u64 {
+    match dir {
+        Direction::Up    => execute_move_up(board),
+        Direction::Down  => execute_move_down(board),
+        Direction::Left  => execute_move_left(board),
+        Direction::Right => execute_move_right(board),
+    }
+}
+
 // Swipe the given board up
-unsafe fn execute_move_0(board: u64) -> u64 {
+fn execute_move_up(board: u64) -> u64 {
     // Every row has a precomputed result, so we simply transpose to convert columns to rows, and combine the
     // results of each row in turn.
     let mut ret = board;
+    let tables = tables();
     let t   = transpose(board);
-    ret ^= COL_UP_TABLE[((t >>  0) & ROW_MASK) as usize] << 0;
-    ret ^= COL_UP_TABLE[((t >> 16) & ROW_MASK) as usize] << 4;
-    ret ^= COL_UP_TABLE[((t >> 32) & ROW_MASK) as usize] << 8;
-    ret ^= COL_UP_TABLE[((t >> 48) & ROW_MASK) as usize] << 12;
+    ret ^= tables.col_up[((t >>  0) & ROW_MASK) as usize] << 0;
+    ret ^= tables.col_up[((t >> 16) & ROW_MASK) as usize] << 4;
+    ret ^= tables.col_up[((t >> 32) & ROW_MASK) as usize] << 8;
+    ret ^= tables.col_up[((t >> 48) & ROW_MASK) as usize] << 12;
     ret
 }
 
 // Swipe the given board down
-unsafe fn execute_move_1(board: u64) -> u64 {
+fn execute_move_down(board: u64) -> u64 {
     let mut ret = board;
+    let tables = tables();
     let t   = transpose(board);
-    ret ^= COL_DOWN_TABLE[((t >>  0) & ROW_MASK) as usize] << 0;
-    ret ^= COL_DOWN_TABLE[((t >> 16) & ROW_MASK) as usize] << 4;
-    ret ^= COL_DOWN_TABLE[((t >> 32) & ROW_MASK) as usize] << 8;
-    ret ^= COL_DOWN_TABLE[((t >> 48) & ROW_MASK) as usize] << 12;
+    ret ^= tables.col_down[((t >>  0) & ROW_MASK) as usize] << 0;
+    ret ^= tables.col_down[((t >> 16) & ROW_MASK) as usize] << 4;
+    ret ^= tables.col_down[((t >> 32) & ROW_MASK) as usize] << 8;
+    ret ^= tables.col_down[((t >> 48) & ROW_MASK) as usize] << 12;
     ret
 }
 
 // Swipe the given board left
-unsafe fn execute_move_2(board: u64) -> u64 {
+fn execute_move_left(board: u64) -> u64 {
     let mut ret = board;
-    ret ^= (ROW_LEFT_TABLE[((board >>  0) & ROW_MASK) as usize] as u64) <<  0;
-    ret ^= (ROW_LEFT_TABLE[((board >> 16) & ROW_MASK) as usize] as u64) << 16;
-    ret ^= (ROW_LEFT_TABLE[((board >> 32) & ROW_MASK) as usize] as u64) << 32;
-    ret ^= (ROW_LEFT_TABLE[((board >> 48) & ROW_MASK) as usize] as u64) << 48;
+    let tables = tables();
+    ret ^= (tables.row_left[((board >>  0) & ROW_MASK) as usize] as u64) <<  0;
+    ret ^= (tables.row_left[((board >> 16) & ROW_MASK) as usize] as u64) << 16;
+    ret ^= (tables.row_left[((board >> 32) & ROW_MASK) as usize] as u64) << 32;
+    ret ^= (tables.row_left[((board >> 48) & ROW_MASK) as usize] as u64) << 48;
     ret
 }
 
 // Swipe the given board right
-unsafe fn execute_move_3(board: u64) -> u64 {
+fn execute_move_right(board: u64) -> u64 {
     let mut ret = board;
-    ret ^= (ROW_RIGHT_TABLE[((board >>  0) & ROW_MASK) as usize] as u64) <<  0;
-    ret ^= (ROW_RIGHT_TABLE[((board >> 16) & ROW_MASK) as usize] as u64) << 16;
-    ret ^= (ROW_RIGHT_TABLE[((board >> 32) & ROW_MASK) as usize] as u64) << 32;
-    ret ^= (ROW_RIGHT_TABLE[((board >> 48) & ROW_MASK) as usize] as u64) << 48;
+    let tables = tables();
+    ret ^= (tables.row_right[((board >>  0) & ROW_MASK) as usize] as u64) <<  0;
+    ret ^= (tables.row_right[((board >> 16) & ROW_MASK) as usize] as u64) << 16;
+    ret ^= (tables.row_right[((board >> 32) & ROW_MASK) as usize] as u64) << 32;
+    ret ^= (tables.row_right[((board >> 48) & ROW_MASK) as usize] as u64) << 48;
     ret
 }
 
+// Returns the points scored by the merges the specified move makes on the given board
+pub fn move_score(dir: Direction, board: u64) -> u32 {
+    let tables = tables();
+    let lines = match dir {
+        Direction::Up | Direction::Down    => transpose(board),
+        Direction::Left | Direction::Right => board,
+    };
+    tables.merge_score[((lines >>  0) & ROW_MASK) as usize] +
+    tables.merge_score[((lines >> 16) & ROW_MASK) as usize] +
+    tables.merge_score[((lines >> 32) & ROW_MASK) as usize] +
+    tables.merge_score[((lines >> 48) & ROW_MASK) as usize]
+}
+
+// Returns a mask of the moves which change the given board. The bit for each direction is dir.mask().
+pub fn legal_moves(board: u64) -> u8 {
+    // Columns are rows of the transpose, and swiping a column up is swiping that row towards its start.
+    let tables = tables();
+    let t = transpose(board);
+    let rows = tables.row_moves[((board >>  0) & ROW_MASK) as usize] |
+               tables.row_moves[((board >> 16) & ROW_MASK) as usize] |
+               tables.row_moves[((board >> 32) & ROW_MASK) as usize] |
+               tables.row_moves[((board >> 48) & ROW_MASK) as usize];
+    let cols = tables.row_moves[((t >>  0) & ROW_MASK) as usize] |
+               tables.row_moves[((t >> 16) & ROW_MASK) as usize] |
+               tables.row_moves[((t >> 32) & ROW_MASK) as usize] |
+               tables.row_moves[((t >> 48) & ROW_MASK) as usize];
+    // Up and down take the low two bits, left and right the next two
+    cols | (rows << 2)
+}
+
+// Returns true if no move can change the given board
+pub fn is_game_over(board: u64) -> bool {
+    legal_moves(board) == 0
+}
+
 // Returns the maximum tile rank (power of 2) present on the given bitboard
 pub fn get_max_rank(mut board: u64) -> u16 {
     let mut maxrank: u16 = 0;
@@ -123,12 +244,31 @@ pub fn count_empty(mut board: u64) -> u64 {
     board += board >>  8;
     board += board >>  4;
     
-    board & 0xF as u64
+    board & 0xF
+}
+
+// The random number generator used to spawn tiles. Games seed it explicitly so they can be replayed.
+pub type GameRng = XorShiftRng;
+
+// Returns a tile spawning generator which is fully determined by the given seed
+pub fn seeded_rng(seed: u64) -> GameRng {
+    // Spread the seed over the generator's state with splitmix64, so that nearby seeds give unrelated games
+    // and the state can never be all zeroes.
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    GameRng::from_seed([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32 | 1])
 }
 
 // Returns a 2 or a 4 tile randomly. 10% chance of a 4.
-pub fn draw_tile() -> u64 {
-    if rand::thread_rng().gen_range(0,10) < 9 {
+pub fn draw_tile<R: Rng>(rng: &mut R) -> u64 {
+    if rng.gen_range(0,10) < 9 {
         1
     } else {
         2
@@ -136,10 +276,10 @@ pub fn draw_tile() -> u64 {
 }
 
 // Inserts the given tile in the given board, in a randomly selected open space.
-pub fn insert_tile_rand(board: u64, mut tile: u64) -> u64 {
+pub fn insert_tile_rand<R: Rng>(board: u64, mut tile: u64, rng: &mut R) -> u64 {
     let empty = count_empty(board) as u32;
     if empty == 0 {return board;} // Cannot insert to a full board.
-    let mut index: u32 = rand::thread_rng().gen_range(0, empty);
+    let mut index: u32 = rng.gen_range(0, empty);
     let mut tmp: u64 = board;
 
     // Find 'index' empty tiles before inserting the tile. That is, insert the tile in the 'index'th empty space
@@ -160,9 +300,10 @@ pub fn insert_tile_rand(board: u64, mut tile: u64) -> u64 {
 }
 
 // Returns a bitboard with two random tiles in it
-pub fn initial_board() -> u64 {
-    let board: u64 = draw_tile() << (4 * rand::thread_rng().gen_range(0, 16));
-    insert_tile_rand(board, draw_tile())
+pub fn initial_board<R: Rng>(rng: &mut R) -> u64 {
+    let board: u64 = draw_tile(rng) << (4 * rng.gen_range(0, 16));
+    let tile = draw_tile(rng);
+    insert_tile_rand(board, tile, rng)
 }
 
 // Prints the bitboard in a human readable format
@@ -173,9 +314,9 @@ pub fn print_board(mut board: u64) {
             print!("{:5},", if power == 0 {0} else {2 << (power-1)}); //2<<power = 2^power
             board >>= 4; //Next byte
         }
-        println!("");
+        println!();
     }
-    println!("");
+    println!();
 }
 
 // Takes a bitboard and returns the transposition of that board
@@ -193,3 +334,159 @@ pub fn transpose(board: u64) -> u64 {
     let b3: u64 = a & 0x00000000FF00FF00;
     b1 | (b2 >> 24) | (b3 << 24)
 }
+
+// Takes a bitboard and returns it reflected left to right
+// a b c d     d c b a
+// e f g h  => h g f e
+// i j k l     l k j i
+// m n o p     p o n m
+pub fn mirror(board: u64) -> u64 {
+    // Swap the nibbles of each byte, then the bytes of each row
+    let a = ((board & 0x0F0F0F0F0F0F0F0F) << 4) | ((board >> 4) & 0x0F0F0F0F0F0F0F0F);
+    ((a & 0x00FF00FF00FF00FF) << 8) | ((a >> 8) & 0x00FF00FF00FF00FF)
+}
+
+// Takes a bitboard and returns it reflected top to bottom
+// a b c d     m n o p
+// e f g h  => i j k l
+// i j k l     e f g h
+// m n o p     a b c d
+pub fn flip(board: u64) -> u64 {
+    // Swap the halves, then the rows of each half
+    let a = board.rotate_left(32);
+    ((a & 0x0000FFFF0000FFFF) << 16) | ((a >> 16) & 0x0000FFFF0000FFFF)
+}
+
+// Takes a bitboard and returns it rotated a quarter turn clockwise
+// a b c d     m i e a
+// e f g h  => n j f b
+// i j k l     o k g c
+// m n o p     p l h d
+pub fn rotate(board: u64) -> u64 {
+    mirror(transpose(board))
+}
+
+// Returns the smallest of the eight boards the given one can be rotated and reflected into. Boards which are
+// rotations or reflections of each other have the same canonical board, and the same value to a search whose
+// heuristic scores a line the same both ways round.
+pub fn canonical(board: u64) -> u64 {
+    let mut smallest = board;
+    for b in [board, transpose(board)] {
+        let flipped = flip(b);
+        smallest = smallest.min(b).min(mirror(b)).min(flipped).min(mirror(flipped));
+    }
+    smallest
+}
+
+// A 4x4 board packed into a 64 bit number, one 4 bit nibble per tile holding the rank (power of 2) of that
+// tile. The first row lives in the lowest 16 bits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Board(pub u64);
+
+impl Board {
+    // Returns a board with two random tiles in it
+    pub fn initial<R: Rng>(rng: &mut R) -> Board {
+        Board(initial_board(rng))
+    }
+
+    // Returns the bitboard underlying this board
+    pub fn raw(self) -> u64 {
+        self.0
+    }
+
+    // Returns the result of the specified move on this board, which is this board unchanged if nothing moves
+    pub fn execute_move(self, dir: Direction) -> Board {
+        Board(execute_move(dir, self.0))
+    }
+
+    // Returns the result of the specified move on this board, or MoveError::NoChange if nothing would move
+    pub fn make_move(self, dir: Direction) -> Result<Board, MoveError> {
+        let newboard = self.execute_move(dir);
+        if newboard == self {
+            Err(MoveError::NoChange)
+        } else {
+            Ok(newboard)
+        }
+    }
+
+    // Returns the points scored by the merges the specified move makes on this board
+    pub fn move_score(self, dir: Direction) -> u32 {
+        move_score(dir, self.0)
+    }
+
+    // Returns a mask of the moves which change this board. The bit for each direction is dir.mask().
+    pub fn legal_moves(self) -> u8 {
+        legal_moves(self.0)
+    }
+
+    // Returns true if no move can change this board
+    pub fn is_game_over(self) -> bool {
+        is_game_over(self.0)
+    }
+
+    // Returns the number of open spaces on this board
+    pub fn count_empty(self) -> u64 {
+        count_empty(self.0)
+    }
+
+    // Returns the maximum tile rank (power of 2) present on this board
+    pub fn get_max_rank(self) -> u16 {
+        get_max_rank(self.0)
+    }
+
+    // Returns the transposition of this board
+    pub fn transpose(self) -> Board {
+        Board(transpose(self.0))
+    }
+
+    // Returns this board reflected left to right
+    pub fn mirror(self) -> Board {
+        Board(mirror(self.0))
+    }
+
+    // Returns this board reflected top to bottom
+    pub fn flip(self) -> Board {
+        Board(flip(self.0))
+    }
+
+    // Returns this board rotated a quarter turn clockwise
+    pub fn rotate(self) -> Board {
+        Board(rotate(self.0))
+    }
+
+    // Returns the smallest of this board's rotations and reflections
+    pub fn canonical(self) -> Board {
+        Board(canonical(self.0))
+    }
+
+    // Returns this board with the given tile inserted in a randomly selected open space
+    pub fn insert_tile_rand<R: Rng>(self, tile: u64, rng: &mut R) -> Board {
+        Board(insert_tile_rand(self.0, tile, rng))
+    }
+
+    // Returns the actual score of this board
+    pub fn score(self) -> f32 {
+        score_board(self.0)
+    }
+}
+
+impl From<u64> for Board {
+    fn from(board: u64) -> Board {
+        Board(board)
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut board = self.0;
+        for _i in 0..4 {
+            for _j in 0..4 {
+                let power = board & 0xf;
+                write!(f, "{:5},", if power == 0 {0} else {2 << (power-1)})?;
+                board >>= 4;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
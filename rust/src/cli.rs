@@ -0,0 +1,337 @@
+// Command line handling for the benchmark harness. Written by hand to keep the dependencies down: options are
+// given as `--name value` or `--name=value`, and any other argument is passed on to the subcommand.
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use twenty48::{Direction, Grid};
+use twenty48::agent::AGENT_NAMES;
+
+pub const USAGE: &str = "\
+Usage: 2048 <command> [options] [arguments]
+
+Commands:
+  bench              Play a set of games for each threshold and summarise them (the default)
+  play               Play a single game and show the moves taken
+  analyze <board>    Show the move the search would make on a board, given as comma separated tiles from
+                     the top left (e.g. 2,0,0,4,...) or, for 4x4 boards, as a hexadecimal bitboard (e.g. 0x1200...)
+  compare            Play the same games with two configurations and test whether their scores differ:
+                     the first two thresholds, or the first threshold with --agent and --agent-b
+  sweep              Play the games at every point of a sweep over search settings and heuristic weights,
+                     and print a table of the results
+  replay <moves>     Replay the game with the given seed and moves, given as letters (e.g. uldr)
+  help               Show this message
+
+Options:
+  --games <n>              Number of games to play for each threshold [default: 5]
+  --agent <name>           Agent to play with: expectimax, random [default: expectimax]
+  --agent-b <name>         Agent to compare against with compare [default: the same as --agent]
+  --thresholds <p,...>     Probability thresholds to search to; play and analyze use the first
+                           [default: 0.01,0.005,0.001,0.0005]
+  --move-time <ms>         Search each move for this long at most, deepening until the time runs out
+  --move-nodes <n>         Search each move for this many nodes at most, deepening until they run out. Each
+                           search then runs on one thread, so that every seed plays the same game
+  --size <w>x<h>           Size of the board to play on: 3x3, 4x4, 4x5, 5x4 or 5x5 [default: 4x4]
+  --seed <n>               Seed of the first game; later games count up from it [default: 1]
+  --threads <n>            Threads to use in all, shared between concurrent games and the search within
+                           each game [default: the number of cores]
+  --game-threads <n>       Games to play at once, at most --threads, leaving the rest of the threads to
+                           their searches
+                           [default: as many as there are threads or games, whichever is fewer]
+  --param <spec>           A parameter for sweep to vary, as name=a,b,c or name=low:high:step, or as
+                           name=low:high to sample from with --samples. May be given more than once.
+                           Parameters: threshold, cache_depth_limit, min_depth, depth_offset,
+                           four_probability, shared_table (0 or 1), cache_generations, cache_mb,
+                           search_cache_mb, canonical_keys (0 or 1), exact_budgets (0 or 1) and the
+                           heuristic weights lost_penalty, monotonicity_power, monotonicity_weight,
+                           sum_power, sum_weight, merges_weight, empty_weight
+  --samples <n>            Make sweep play this many random points instead of every combination
+  --confidence <c>         Confidence level of intervals and tests [default: 0.95]
+  --format <format>        Output format: text, csv or jsonl [default: text]
+  --output <file>          Also write a record of every game and every aggregate to this file, as CSV if
+                           its name ends in .csv and as JSON Lines otherwise (bench, compare and sweep)
+  --search-log <file>      Also write the search for every move of every game to this file: the value of each
+                           move, the depth reached, the nodes and cache hits and the time taken, in the same
+                           formats as --output
+";
+
+// The board sizes the harness can play on, as width and height
+pub const SIZES: [(usize, usize); 5] = [(3, 3), (4, 4), (4, 5), (5, 4), (5, 5)];
+
+// The subcommands of the harness
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Bench,
+    Compare,
+    Play,
+    Analyze,
+    Replay,
+    Sweep,
+    Help,
+}
+
+// The formats results can be written in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Text,
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text"  => Ok(Format::Text),
+            "csv"   => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            _       => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+// Everything given on the command line, with defaults filled in
+#[derive(Clone, PartialEq, Debug)]
+pub struct Options {
+    pub command: Command,
+    pub games: u64,
+    pub agent: String,
+    pub agent_b: Option<String>,
+    pub thresholds: Vec<f32>,
+    pub move_time: Option<Duration>,
+    pub move_nodes: Option<u64>,
+    pub size: (usize, usize),
+    pub seed: u64,
+    pub threads: usize,
+    pub game_threads: Option<usize>,
+    pub params: Vec<String>,
+    pub samples: Option<usize>,
+    pub confidence: f64,
+    pub format: Format,
+    pub output: Option<String>,
+    pub search_log: Option<String>,
+    pub args: Vec<String>, // The arguments which are not options, after the command
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: Command::Bench,
+            games: 5,
+            agent: AGENT_NAMES[0].to_string(),
+            agent_b: None,
+            thresholds: vec!(0.01, 0.005, 0.001, 0.0005),
+            move_time: None,
+            move_nodes: None,
+            size: (4, 4),
+            seed: 1,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            game_threads: None,
+            params: vec!(),
+            samples: None,
+            confidence: 0.95,
+            format: Format::Text,
+            output: None,
+            search_log: None,
+            args: vec!(),
+        }
+    }
+}
+
+// Reads the options from the given arguments, not including the program name
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut command = None;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if command.is_none() {
+                command = Some(parse_command(&arg)?);
+            } else {
+                options.args.push(arg);
+            }
+            continue;
+        }
+
+        // Split off an inline value, otherwise the value is the next argument
+        let (name, inline) = match arg.find('=') {
+            Some(i) => (arg[2..i].to_string(), Some(arg[i + 1..].to_string())),
+            None    => (arg[2..].to_string(), None),
+        };
+        if name == "help" {
+            command = Some(Command::Help);
+            continue;
+        }
+        let value = match inline.or_else(|| args.next()) {
+            Some(value) => value,
+            None        => return Err(format!("--{} needs a value", name)),
+        };
+
+        match name.as_str() {
+            "games"      => options.games = parse_value(&name, &value)?,
+            "agent"      => options.agent = value,
+            "agent-b"    => options.agent_b = Some(value),
+            "thresholds" | "threshold" => {
+                options.thresholds = value.split(',').map(|v| parse_value(&name, v)).collect::<Result<_, _>>()?;
+            }
+            "move-time"  => {
+                let ms: f64 = parse_value(&name, &value)?;
+                if !(ms > 0.0 && ms.is_finite()) {
+                    return Err(format!("--move-time must be a positive number of milliseconds, not {}", value));
+                }
+                options.move_time = Some(Duration::from_secs_f64(ms / 1000.0));
+            }
+            "move-nodes" => options.move_nodes = Some(parse_value(&name, &value)?),
+            "size"       => options.size = parse_size(&value)?,
+            "seed"       => options.seed = parse_value(&name, &value)?,
+            "threads"    => options.threads = parse_value(&name, &value)?,
+            "game-threads" => options.game_threads = Some(parse_value(&name, &value)?),
+            "param"      => options.params.push(value),
+            "samples"    => options.samples = Some(parse_value(&name, &value)?),
+            "confidence" => options.confidence = parse_value(&name, &value)?,
+            "format"     => options.format = value.parse()?,
+            "output"     => options.output = Some(value),
+            "search-log" => options.search_log = Some(value),
+            _            => return Err(format!("unknown option --{}", name)),
+        }
+    }
+
+    options.command = command.unwrap_or(Command::Bench);
+    for agent in Some(&options.agent).into_iter().chain(options.agent_b.as_ref()) {
+        if !AGENT_NAMES.contains(&agent.as_str()) {
+            return Err(format!("unknown agent '{}'", agent));
+        }
+    }
+    if !(options.confidence > 0.0 && options.confidence < 1.0) {
+        return Err("--confidence must be between 0 and 1".to_string());
+    }
+    if options.thresholds.is_empty() {
+        return Err("at least one threshold is needed".to_string());
+    }
+    if options.threads == 0 || options.game_threads == Some(0) {
+        return Err("--threads and --game-threads must be at least 1".to_string());
+    }
+    if options.game_threads.is_some_and(|games| games > options.threads) {
+        return Err("--game-threads cannot be more than --threads".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_command(arg: &str) -> Result<Command, String> {
+    match arg {
+        "bench"   => Ok(Command::Bench),
+        "compare" => Ok(Command::Compare),
+        "play"    => Ok(Command::Play),
+        "analyze" => Ok(Command::Analyze),
+        "replay"  => Ok(Command::Replay),
+        "sweep"   => Ok(Command::Sweep),
+        "help"    => Ok(Command::Help),
+        _         => Err(format!("unknown command '{}'", arg)),
+    }
+}
+
+// Reads a board size written as width x height, such as 4x5
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let size = match value.split_once('x') {
+        Some((width, height)) => (parse_value("size", width)?, parse_value("size", height)?),
+        None                  => return Err(format!("invalid value '{}' for --size", value)),
+    };
+    if !SIZES.contains(&size) {
+        return Err(format!("boards of size {} are not supported", value));
+    }
+    Ok(size)
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("invalid value '{}' for --{}", value, name))
+}
+
+// Reads a board from its tile values, comma separated from the top left along the rows, or a 4x4 board from a
+// hexadecimal bitboard
+pub fn parse_board<const W: usize, const H: usize>(arg: &str) -> Result<Grid<W, H>, String> {
+    let cells = Grid::<W, H>::CELLS;
+    if let Some(hex) = arg.strip_prefix("0x") {
+        let raw = u64::from_str_radix(hex, 16).map_err(|_| format!("invalid bitboard '{}'", arg))?;
+        if W != 4 || H != 4 {
+            return Err(format!("a bitboard only holds a 4x4 board, not a {}x{} one", W, H));
+        }
+        return Ok((0..cells).fold(Grid::empty(), |grid, cell| grid.with_rank(cell, ((raw >> (4 * cell)) & 0xF) as u8)));
+    }
+
+    let tiles: Vec<&str> = arg.split(',').collect();
+    if tiles.len() != cells {
+        return Err(format!("a board needs {} tiles, not {}", cells, tiles.len()));
+    }
+    let mut grid = Grid::empty();
+    for (cell, tile) in tiles.iter().enumerate() {
+        let value: u64 = parse_value("board", tile)?;
+        if value == 1 || value & value.wrapping_sub(1) != 0 {
+            return Err(format!("{} is not a tile", value));
+        }
+        let rank = if value == 0 {0} else {value.trailing_zeros() as u8};
+        grid = grid.with_rank(cell, rank);
+    }
+    Ok(grid)
+}
+
+// Reads a list of moves written as letters, such as "uldr"
+pub fn parse_moves(arg: &str) -> Result<Vec<Direction>, String> {
+    arg.chars().filter(|c| !c.is_whitespace() && *c != ',').map(|c| c.to_string().parse()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_args(args.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_are_read_in_either_form_around_the_command() {
+        let options = parse("--games 3 play --thresholds=0.1,0.2 --format csv extra --size 4x5").unwrap();
+        assert_eq!(options.command, Command::Play);
+        assert_eq!(options.games, 3);
+        assert_eq!(options.thresholds, vec!(0.1, 0.2));
+        assert_eq!(options.format, Format::Csv);
+        assert_eq!(options.size, (4, 5));
+        assert_eq!(options.args, vec!("extra".to_string()));
+
+        assert_eq!(parse("").unwrap(), Options::default());
+        assert_eq!(parse("bench --help").unwrap().command, Command::Help);
+        assert_eq!(parse("--move-time 2.5").unwrap().move_time, Some(Duration::from_micros(2500)));
+    }
+
+    #[test]
+    fn bad_options_are_refused() {
+        for args in ["--games", "--games many", "--colour red", "dance", "--agent nobody", "--confidence 1",
+                     "--thresholds=", "--threads 0", "--move-time 0", "--size 6x6", "--size 4", "--format xml",
+                     "--threads 2 --game-threads 3"] {
+            assert!(parse(args).is_err(), "{} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn boards_are_read_from_tiles_or_a_bitboard() {
+        let tiles = "2,0,0,4,0,0,0,0,0,0,0,0,0,0,0,65536";
+        let grid = parse_board::<4, 4>(tiles).unwrap();
+        assert_eq!((grid.rank(0), grid.rank(3), grid.rank(15)), (1, 2, 16));
+        // The first cell is the lowest nibble
+        assert_eq!(parse_board::<4, 4>("0x1200"), parse_board::<4, 4>("0,0,4,2,0,0,0,0,0,0,0,0,0,0,0,0"));
+
+        let grid = parse_board::<3, 3>("0,0,0,0,8,0,0,0,0").unwrap();
+        assert_eq!(grid.rank(4), 3);
+        for board in ["2,0,0,4", "3,0,0,0,0,0,0,0,0", "1,0,0,0,0,0,0,0,0", "0x12"] {
+            assert!(parse_board::<3, 3>(board).is_err(), "{} was accepted", board);
+        }
+        assert!(parse_board::<4, 4>("0xZZ").is_err());
+    }
+
+    #[test]
+    fn moves_are_read_from_letters() {
+        assert_eq!(parse_moves("ul, dr"), Ok(vec!(Direction::Up, Direction::Left, Direction::Down, Direction::Right)));
+        assert!(parse_moves("ux").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+// CPU clocks, which count the time a thread or the whole process spent working rather than the time that passed.
+use std::time::Duration;
+
+use libc;
+
+// The CPU clocks which can be read
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CpuClock {
+    Process, // CPU time used by every thread in the process, including ones which have finished
+    Thread,  // CPU time used by the calling thread
+}
+
+// Returns the CPU time used so far on the given clock, or None if it cannot be read on this platform
+#[cfg(unix)]
+pub fn cpu_time(clock: CpuClock) -> Option<Duration> {
+    let id = match clock {
+        CpuClock::Process => libc::CLOCK_PROCESS_CPUTIME_ID,
+        CpuClock::Thread  => libc::CLOCK_THREAD_CPUTIME_ID,
+    };
+    let mut time = libc::timespec {tv_sec: 0, tv_nsec: 0};
+    // SAFETY: clock_gettime only writes the timespec it is given, which lives for the whole call
+    if unsafe { libc::clock_gettime(id, &mut time) } != 0 {
+        return None;
+    }
+    Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(not(unix))]
+pub fn cpu_time(_clock: CpuClock) -> Option<Duration> {
+    None
+}
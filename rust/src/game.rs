@@ -0,0 +1,135 @@
+use board::{Board, Direction, MoveError, GameRng};
+use board::seeded_rng;
+use grid::Grid;
+use spawn::SpawnRules;
+
+// A tile placed on the board by the game, either at the start or after a move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Spawn {
+    pub cell: u8, // Index of the cell the tile went in, counting along the rows from the top left
+    pub rank: u8, // Rank (power of 2) of the tile, e.g. 1 for a 2 and 2 for a 4
+}
+
+// A game of 2048 in progress. Owns the board and the tile spawning generator, and keeps an exact record of
+// everything that has happened, so that the seed and the moves are enough to replay it.
+// The board is held with a byte per tile, so games carry on past 32768 with the tiles and score intact.
+// Any board size can be played; Game is the standard 4x4 game.
+pub struct GridGame<const W: usize, const H: usize> {
+    seed: u64,
+    rng: GameRng,
+    rules: SpawnRules,
+    board: Grid<W, H>,
+    score: u64,              // The true 2048 score: the value of every tile created by a merge
+    moves: Vec<Direction>,   // Every move taken, in order
+    spawns: Vec<Spawn>,      // Every tile spawned, in order, including the initial tiles
+}
+
+// The standard 4x4 game
+pub type Game = GridGame<4, 4>;
+
+impl<const W: usize, const H: usize> GridGame<W, H> {
+    // Starts a new game with the standard spawn rules, whose tile spawns are fully determined by the given seed
+    pub fn new(seed: u64) -> GridGame<W, H> {
+        GridGame::with_rules(seed, SpawnRules::default())
+    }
+
+    // Starts a new game with the given spawn rules, whose tile spawns are fully determined by the given seed
+    pub fn with_rules(seed: u64, rules: SpawnRules) -> GridGame<W, H> {
+        let mut game = GridGame {
+            seed,
+            rng: seeded_rng(seed),
+            rules,
+            board: Grid::empty(),
+            score: 0,
+            moves: vec!(),
+            spawns: vec!(),
+        };
+
+        for _ in 0..game.rules.initial {
+            game.spawn_tile();
+        }
+        game
+    }
+
+    // Plays the given move and spawns the next tiles. Returns the points scored by the move, or
+    // MoveError::NoChange (leaving the game untouched) if the move does not change the board.
+    pub fn play(&mut self, dir: Direction) -> Result<u64, MoveError> {
+        let (newboard, points) = self.board.make_move(dir)?;
+
+        self.board = newboard;
+        self.score += points;
+        self.moves.push(dir);
+        for _ in 0..self.rules.per_turn {
+            self.spawn_tile();
+        }
+        Ok(points)
+    }
+
+    // Inserts a random tile in a random open space and records where it went
+    fn spawn_tile(&mut self) {
+        let tile = match self.rules.draw_tile(&mut self.rng) {
+            Some(tile) => tile,
+            None       => return,
+        };
+        if let Some(cell) = self.board.insert_tile_rand(tile, &mut self.rng) {
+            self.spawns.push(Spawn {cell: cell as u8, rank: tile});
+        }
+    }
+
+    // Returns the seed which decides this game's tile spawns
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Returns the rules this game spawns tiles by
+    pub fn rules(&self) -> &SpawnRules {
+        &self.rules
+    }
+
+    // Returns the exact current board
+    pub fn grid(&self) -> Grid<W, H> {
+        self.board
+    }
+
+    // Returns the score so far
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    // Returns the number of moves played so far
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    // Returns every move played so far, in order
+    pub fn moves(&self) -> &[Direction] {
+        &self.moves
+    }
+
+    // Returns every tile spawned so far, in order
+    pub fn spawns(&self) -> &[Spawn] {
+        &self.spawns
+    }
+
+    // Returns the highest tile rank (power of 2) on the board
+    pub fn max_rank(&self) -> u16 {
+        self.board.max_rank()
+    }
+
+    // Returns a mask of the moves which change the board. The bit for each direction is dir.mask().
+    pub fn legal_moves(&self) -> u8 {
+        self.board.legal_moves()
+    }
+
+    // Returns true once no move can change the board
+    pub fn is_over(&self) -> bool {
+        self.board.is_game_over()
+    }
+}
+
+impl Game {
+    // Returns the current board as a bitboard, for the search. Tiles above 32768 are shown as 32768s.
+    pub fn board(&self) -> Board {
+        self.board.to_board()
+    }
+}
@@ -1,24 +1,47 @@
-use super::ROW_LEFT_TABLE;
-use super::ROW_RIGHT_TABLE;
-use super::COL_UP_TABLE;
-use super::COL_DOWN_TABLE;
-use super::HEUR_SCORE_TABLE;
-use super::SCORE_TABLE;
+use std::sync::OnceLock;
 
-use super::board::*;
+use board::{reverse_row, unpack_col};
 
-// Constants to tune game behaviour
-const SCORE_LOST_PENALTY:       f32 = 200000.0;
-const SCORE_MONOTONICITY_POWER: f32 = 4.0;
-const SCORE_MONOTONICITY_WEIGHT:f32 = 47.0;
-const SCORE_SUM_POWER:          f32 = 3.5;
-const SCORE_SUM_WEIGHT:         f32 = 11.0;
-const SCORE_MERGES_WEIGHT:      f32 = 700.0;
-const SCORE_EMPTY_WEIGHT:       f32 = 270.0;
 
+// Tables which are filled with precomputed moves. Any row XORed with row_left[row] will be the result
+// of swiping that row left, and so on with the other directions.
+// Precomputed scores for single rows are kept alongside. Heuristics keep their own tables.
+pub struct Tables {
+    pub row_left:   Box<[u16]>,
+    pub row_right:  Box<[u16]>,
+    pub col_up:     Box<[u64]>,
+    pub col_down:   Box<[u64]>,
+    pub score:      Box<[f32]>,
+    // For each row, bit 0 is set if swiping it towards its first tile changes it and bit 1 if swiping it
+    // towards its last tile does.
+    pub row_moves:  Box<[u8]>,
+    // Points scored by the merges made when swiping each row. Merges pair up the same tiles whichever way
+    // the row is swiped, so one table serves both directions.
+    pub merge_score: Box<[u32]>,
+}
+
+// Returns the shared precomputed tables, building them on first use. Safe to call from any thread.
+pub fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(build_tables)
+}
+
+// Forces the tables to be built now rather than on first use, e.g. to keep the cost out of a timed section.
+pub fn init_tables() {
+    tables();
+}
+
+// Builds the precomputed tables used to execute moves and score states
+#[allow(clippy::needless_range_loop)]
+fn build_tables() -> Tables {
+    let mut row_left_table   = vec![0u16; 65536].into_boxed_slice();
+    let mut row_right_table  = vec![0u16; 65536].into_boxed_slice();
+    let mut col_up_table     = vec![0u64; 65536].into_boxed_slice();
+    let mut col_down_table   = vec![0u64; 65536].into_boxed_slice();
+    let mut score_table      = vec![0f32; 65536].into_boxed_slice();
+    let mut row_moves_table  = vec![0u8;  65536].into_boxed_slice();
+    let mut merge_score_table = vec![0u32; 65536].into_boxed_slice();
 
-// Initialises the precomputed tables used to execute moves and score states
-pub unsafe fn init_tables() {
     // Each possible row (16 bit number) has its results precomputed
     for row in 0..65536usize {
         // Convert the 16 bit number into an array of 4 parts (effectively 4 bit numbers)
@@ -37,53 +60,10 @@ pub unsafe fn init_tables() {
                 score += (rank as f32 - 1.0) * (1 << rank) as f32;
             }
         }
-        SCORE_TABLE[row as usize] = score;
+        score_table[row] = score;
 
-        // Calculate merges
-        let mut sum: f32 = 0.0;
-        let mut empty = 0;
-        let mut merges = 0;
-
-        let mut prev = 0;
-        let mut counter = 0;
-        for i in 0..4 {
-            let rank = line[i];
-            sum += (rank as f32).powf(SCORE_SUM_POWER);
-            if rank == 0 {
-                empty += 1;
-            } else {
-                if prev == rank {
-                    counter += 1;
-                } else if counter > 0 {
-                    merges += 1 + counter;
-                    counter = 0;
-                }
-                prev = rank;
-            }
-        }
-        if counter > 0 {
-            merges += 1 + counter;
-        }
-
-        // Calculate monotonicity
-        let mut monotonicity_left : f32 = 0.0;
-        let mut monotonicity_right: f32 = 0.0;
-        for i in 1..4 {
-            if line[i-1] > line[i] {
-                monotonicity_left += (line[i-1] as f32).powf(SCORE_MONOTONICITY_POWER) - (line[i] as f32).powf(SCORE_MONOTONICITY_POWER);
-            } else {
-                monotonicity_right += (line[i] as f32).powf(SCORE_MONOTONICITY_POWER) - (line[i-1] as f32).powf(SCORE_MONOTONICITY_POWER);
-            }
-        }
-        
-        // Combine the components of the heuristic into one value
-        HEUR_SCORE_TABLE[row as usize] = SCORE_LOST_PENALTY + 
-            SCORE_EMPTY_WEIGHT * empty as f32 +
-            SCORE_MERGES_WEIGHT * merges as f32 -
-            SCORE_MONOTONICITY_WEIGHT * monotonicity_left.min(monotonicity_right)-
-            SCORE_SUM_WEIGHT * sum as f32;
-
-        //Exectute a move to the left
+        //Exectute a move to the left, totalling the value of the tiles created by merges
+        let mut merge_score: u32 = 0;
         let mut i = 0;
         while i < 3 {
             let mut j = i + 1;
@@ -106,6 +86,7 @@ pub unsafe fn init_tables() {
                 if line[i] != 0xF {
                     line[i] += 1;
                 }
+                merge_score += 1 << line[i];
                 line[j] = 0;
             }
             i+= 1;
@@ -120,9 +101,25 @@ pub unsafe fn init_tables() {
         let rev_row   : u16 = reverse_row(row as u16);
         
         // The result of each move is simply some modification of the left move, so we can store them all
-        ROW_LEFT_TABLE  [    row]          =                row as u16  ^                result;
-        ROW_RIGHT_TABLE [rev_row as usize] =            rev_row         ^            rev_result;
-        COL_UP_TABLE    [    row]          = unpack_col(    row as u16) ^ unpack_col(    result);
-        COL_DOWN_TABLE  [rev_row as usize] = unpack_col(rev_row)        ^ unpack_col(rev_result);
-    }            
+        row_left_table  [    row]          =                row as u16  ^                result;
+        row_right_table [rev_row as usize] =            rev_row         ^            rev_result;
+        col_up_table    [    row]          = unpack_col(    row as u16) ^ unpack_col(    result);
+        col_down_table  [rev_row as usize] = unpack_col(rev_row)        ^ unpack_col(rev_result);
+        merge_score_table[row]             = merge_score;
+    }
+
+    // A row can be swiped in a direction exactly when the move table has something to change for it
+    for row in 0..65536usize {
+        row_moves_table[row] = (row_left_table[row] != 0) as u8 | ((row_right_table[row] != 0) as u8) << 1;
+    }
+
+    Tables {
+        row_left:   row_left_table,
+        row_right:  row_right_table,
+        col_up:     col_up_table,
+        col_down:   col_down_table,
+        score:      score_table,
+        row_moves:  row_moves_table,
+        merge_score: merge_score_table,
+    }
 }
@@ -0,0 +1,245 @@
+use rand::Rng;
+use std::fmt;
+
+use board::{Board, Direction, MoveError};
+use generate_tables::tables;
+use heuristic::Heuristic;
+
+// The highest rank a line can hold for the 4 bit row tables to swipe it. Merging two of these makes a 15,
+// the most a nibble holds, so only lines with bigger tiles have to be swiped the slow way.
+const TABLE_MAX_RANK: u8 = 14;
+
+// A board of any size with a full byte per tile, so it also holds tiles beyond the 32768 a bitboard nibble
+// can. Cells are numbered along the rows from the top left, in the same order as the bitboard's nibbles.
+// Up is towards the first row and left is towards the first column, as for the bitboard.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Grid<const W: usize, const H: usize> {
+    ranks: [[u8; W]; H],
+}
+
+// The standard 4x4 board, held exactly
+pub type WideBoard = Grid<4, 4>;
+
+impl<const W: usize, const H: usize> Grid<W, H> {
+    // The number of cells on the board
+    pub const CELLS: usize = W * H;
+
+    // Returns a board with no tiles on it
+    pub fn empty() -> Grid<W, H> {
+        Grid { ranks: [[0; W]; H] }
+    }
+
+    // Returns the rank (power of 2) of the tile in the given cell, or 0 if it is empty
+    pub fn rank(&self, cell: usize) -> u8 {
+        self.ranks[cell / W][cell % W]
+    }
+
+    // Returns this board with the tile in the given cell set to the given rank
+    pub fn with_rank(mut self, cell: usize, rank: u8) -> Grid<W, H> {
+        self.ranks[cell / W][cell % W] = rank;
+        self
+    }
+
+    // Returns the ranks in the given column, from the top
+    fn column(&self, c: usize) -> [u8; H] {
+        let mut col = [0u8; H];
+        for (rank, row) in col.iter_mut().zip(self.ranks.iter()) {
+            *rank = row[c];
+        }
+        col
+    }
+
+    // Returns the bitboard for this board if it is 4x4, which the search handles much faster. Tiles above
+    // 32768 do not fit in a nibble, so they are shown as 32768s; the result is exact whenever max_rank() < 16.
+    pub fn to_bitboard(&self) -> Option<Board> {
+        if W != 4 || H != 4 {
+            return None;
+        }
+        let mut board = 0;
+        for cell in 0..Self::CELLS {
+            board |= (self.rank(cell).min(0xF) as u64) << (4 * cell);
+        }
+        Some(Board(board))
+    }
+
+    // Returns the result of the specified move on this board and the points scored by its merges. A move
+    // which changes nothing returns the board unchanged.
+    pub fn execute_move(&self, dir: Direction) -> (Grid<W, H>, u64) {
+        let mut result = *self;
+        let mut points = 0;
+        match dir {
+            Direction::Left | Direction::Right => {
+                for row in result.ranks.iter_mut() {
+                    points += swipe_line(row, dir == Direction::Right);
+                }
+            }
+            Direction::Up | Direction::Down => {
+                for c in 0..W {
+                    let mut col = result.column(c);
+                    points += swipe_line(&mut col, dir == Direction::Down);
+                    for (row, &rank) in result.ranks.iter_mut().zip(col.iter()) {
+                        row[c] = rank;
+                    }
+                }
+            }
+        }
+        (result, points)
+    }
+
+    // Returns the result of the specified move on this board and the points scored by its merges, or
+    // MoveError::NoChange if nothing would move
+    pub fn make_move(&self, dir: Direction) -> Result<(Grid<W, H>, u64), MoveError> {
+        let (newboard, points) = self.execute_move(dir);
+        if newboard == *self {
+            Err(MoveError::NoChange)
+        } else {
+            Ok((newboard, points))
+        }
+    }
+
+    // Returns a mask of the moves which change this board. The bit for each direction is dir.mask().
+    pub fn legal_moves(&self) -> u8 {
+        // Without big tiles the 4x4 bitboard is exact, so its tables can answer directly
+        if let Some(board) = self.to_bitboard() {
+            if self.max_rank() <= TABLE_MAX_RANK as u16 {
+                return board.legal_moves();
+            }
+        }
+        let mut mask = 0;
+        for &dir in &Direction::ALL {
+            if self.execute_move(dir).0 != *self {
+                mask |= dir.mask();
+            }
+        }
+        mask
+    }
+
+    // Returns true if no move can change this board
+    pub fn is_game_over(&self) -> bool {
+        self.legal_moves() == 0
+    }
+
+    // Returns the maximum tile rank (power of 2) present on this board
+    pub fn max_rank(&self) -> u16 {
+        self.ranks.iter().flat_map(|row| row.iter()).cloned().max().unwrap_or(0) as u16
+    }
+
+    // Returns the number of open spaces on this board
+    pub fn count_empty(&self) -> u64 {
+        self.ranks.iter().flat_map(|row| row.iter()).filter(|&&rank| rank == 0).count() as u64
+    }
+
+    // Returns the number of unique tiles on the board, counted the same way as for the bitboard
+    pub fn count_distinct_tiles(&self) -> u32 {
+        let mut bitset: u64 = 0;
+        for &rank in self.ranks.iter().flat_map(|row| row.iter()) {
+            bitset |= 1 << rank.min(63);
+        }
+        (bitset >> 1).count_ones().max(2)
+    }
+
+    // Returns the score of the board under the given heuristic, summed over every row and column
+    pub fn heuristic<E: Heuristic + ?Sized>(&self, heuristic: &E) -> f32 {
+        let mut score = 0.0;
+        for row in &self.ranks {
+            score += heuristic.score_line(row);
+        }
+        for c in 0..W {
+            score += heuristic.score_line(&self.column(c));
+        }
+        score
+    }
+
+    // Inserts a tile of the given rank in a randomly selected open space, and returns the cell it went in.
+    // Draws from the generator exactly as the bitboard's insert_tile_rand does.
+    pub fn insert_tile_rand<R: Rng>(&mut self, rank: u8, rng: &mut R) -> Option<usize> {
+        let empty = self.count_empty() as u32;
+        if empty == 0 {return None;} // Cannot insert to a full board.
+        let index = rng.gen_range(0, empty) as usize;
+
+        let cell = (0..Self::CELLS).filter(|&cell| self.rank(cell) == 0).nth(index).unwrap();
+        *self = self.with_rank(cell, rank);
+        Some(cell)
+    }
+}
+
+impl From<Board> for WideBoard {
+    fn from(board: Board) -> WideBoard {
+        let mut grid = WideBoard::empty();
+        for cell in 0..WideBoard::CELLS {
+            grid = grid.with_rank(cell, ((board.raw() >> (4 * cell)) & 0xF) as u8);
+        }
+        grid
+    }
+}
+
+impl WideBoard {
+    // Returns the bitboard for this board. Tiles above 32768 are shown as 32768s.
+    pub fn to_board(&self) -> Board {
+        self.to_bitboard().unwrap()
+    }
+}
+
+impl<const W: usize, const H: usize> fmt::Display for Grid<W, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in &self.ranks {
+            for &rank in row {
+                write!(f, "{:7},", if rank == 0 {0} else {2u64 << (rank-1)})?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Swipes a line of ranks towards its first cell, or its last if reversed, and returns the points scored by
+// its merges
+fn swipe_line(ranks: &mut [u8], reversed: bool) -> u64 {
+    if reversed {
+        ranks.reverse();
+    }
+
+    // Lines of 4 without big tiles are looked up in the row tables, which treat the first nibble as the
+    // first cell
+    let points = if ranks.len() == 4 && ranks.iter().all(|&rank| rank <= TABLE_MAX_RANK) {
+        let tables = tables();
+        let row = ranks.iter().rev().fold(0usize, |row, &rank| (row << 4) | rank as usize);
+        let result = row ^ tables.row_left[row] as usize;
+        for (i, rank) in ranks.iter_mut().enumerate() {
+            *rank = ((result >> (4 * i)) & 0xF) as u8;
+        }
+        tables.merge_score[row] as u64
+    } else {
+        slide_line(ranks)
+    };
+
+    if reversed {
+        ranks.reverse();
+    }
+    points
+}
+
+// Slides a line of ranks of any size towards its first cell, merging equal neighbours once each, and returns
+// the points scored by the merges.
+pub fn slide_line(ranks: &mut [u8]) -> u64 {
+    let mut points = 0;
+    let mut target = 0;          // The next cell to fill
+    let mut mergeable = false;   // Whether the tile before target can still merge
+    for i in 0..ranks.len() {
+        let rank = ranks[i];
+        if rank == 0 {
+            continue;
+        }
+        ranks[i] = 0;
+        if mergeable && ranks[target - 1] == rank {
+            ranks[target - 1] += 1;
+            points += 1u64 << (rank + 1);
+            mergeable = false;
+        } else {
+            ranks[target] = rank;
+            target += 1;
+            mergeable = true;
+        }
+    }
+    points
+}
@@ -0,0 +1,154 @@
+use super::ROW_MASK;
+use board::transpose;
+use scoring::score_helper;
+
+// Evaluates the leaves of the expectimax search. Boards are scored line by line, so the same heuristic works
+// for every board size; the 4x4 bitboard gets its own entry point so implementations can use row tables.
+pub trait Heuristic: Sync {
+    // Returns the heuristic value of a single row or column of ranks, given from one end to the other
+    fn score_line(&self, line: &[u8]) -> f32;
+
+    // Returns the heuristic value of a 4x4 bitboard: score_line summed over its rows and columns
+    fn score_board(&self, board: u64) -> f32 {
+        let mut score = 0.0;
+        for &lines in &[board, transpose(board)] {
+            for i in 0..4 {
+                let row = (lines >> (16 * i)) & ROW_MASK;
+                let line = [
+                    ((row >>  0) & 0xF) as u8,
+                    ((row >>  4) & 0xF) as u8,
+                    ((row >>  8) & 0xF) as u8,
+                    ((row >> 12) & 0xF) as u8,
+                ];
+                score += self.score_line(&line);
+            }
+        }
+        score
+    }
+}
+
+// The weights of the row heuristic. Defaults to the tuned values from the original engine.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RowWeights {
+    pub lost_penalty:        f32,
+    pub monotonicity_power:  f32,
+    pub monotonicity_weight: f32,
+    pub sum_power:           f32,
+    pub sum_weight:          f32,
+    pub merges_weight:       f32,
+    pub empty_weight:        f32,
+}
+
+impl Default for RowWeights {
+    fn default() -> RowWeights {
+        RowWeights {
+            lost_penalty:        200000.0,
+            monotonicity_power:  4.0,
+            monotonicity_weight: 47.0,
+            sum_power:           3.5,
+            sum_weight:          11.0,
+            merges_weight:       700.0,
+            empty_weight:        270.0,
+        }
+    }
+}
+
+impl RowWeights {
+    // Returns the heuristic value of a single line of ranks, of any length
+    pub fn score_line(&self, line: &[u8]) -> f32 {
+        // Calculate merges
+        let mut sum: f32 = 0.0;
+        let mut empty = 0;
+        let mut merges = 0;
+
+        let mut prev = 0;
+        let mut counter = 0;
+        for &rank in line {
+            sum += (rank as f32).powf(self.sum_power);
+            if rank == 0 {
+                empty += 1;
+            } else {
+                if prev == rank {
+                    counter += 1;
+                } else if counter > 0 {
+                    merges += 1 + counter;
+                    counter = 0;
+                }
+                prev = rank;
+            }
+        }
+        if counter > 0 {
+            merges += 1 + counter;
+        }
+
+        // Calculate monotonicity
+        let mut monotonicity_left : f32 = 0.0;
+        let mut monotonicity_right: f32 = 0.0;
+        for i in 1..line.len() {
+            if line[i-1] > line[i] {
+                monotonicity_left += (line[i-1] as f32).powf(self.monotonicity_power) - (line[i] as f32).powf(self.monotonicity_power);
+            } else {
+                monotonicity_right += (line[i] as f32).powf(self.monotonicity_power) - (line[i-1] as f32).powf(self.monotonicity_power);
+            }
+        }
+
+        // Combine the components of the heuristic into one value
+        self.lost_penalty +
+            self.empty_weight * empty as f32 +
+            self.merges_weight * merges as f32 -
+            self.monotonicity_weight * monotonicity_left.min(monotonicity_right)-
+            self.sum_weight * sum
+    }
+}
+
+// The engine's heuristic: rewards empty cells, merge opportunities and monotonic rows, and penalises large
+// tiles. Every 4 tile row is precomputed into a table owned by this instance, so several differently weighted
+// heuristics can be used at once.
+pub struct RowHeuristic {
+    weights: RowWeights,
+    table: Box<[f32]>,
+}
+
+impl RowHeuristic {
+    // Builds the heuristic and its row table for the given weights
+    pub fn new(weights: RowWeights) -> RowHeuristic {
+        let mut table = vec![0f32; 65536].into_boxed_slice();
+        for (row, score) in table.iter_mut().enumerate() {
+            let line = [
+                ((row >>  0) & 0xF) as u8,
+                ((row >>  4) & 0xF) as u8,
+                ((row >>  8) & 0xF) as u8,
+                ((row >> 12) & 0xF) as u8,
+            ];
+            *score = weights.score_line(&line);
+        }
+        RowHeuristic { weights, table }
+    }
+
+    // Returns the weights this heuristic was built with
+    pub fn weights(&self) -> RowWeights {
+        self.weights
+    }
+}
+
+impl Default for RowHeuristic {
+    fn default() -> RowHeuristic {
+        RowHeuristic::new(RowWeights::default())
+    }
+}
+
+impl Heuristic for RowHeuristic {
+    fn score_line(&self, line: &[u8]) -> f32 {
+        if line.len() == 4 && line.iter().all(|&rank| rank <= 0xF) {
+            let row = line.iter().rev().fold(0usize, |row, &rank| (row << 4) | rank as usize);
+            return self.table[row];
+        }
+        self.weights.score_line(line)
+    }
+
+    fn score_board(&self, board: u64) -> f32 {
+        // Consider the board and the transpose because things like monotonicity matter in the x and y directions
+        score_helper(          board , &self.table) +
+        score_helper(transpose(board), &self.table)
+    }
+}
@@ -0,0 +1,33 @@
+// An implementation of 2048 on boards of any size, with a fast 4x4 bitboard, and an expectimax player for it.
+// Heavily inspired by the cpp implementation on github by user 'nneonneo'
+#![allow(clippy::identity_op)] // Shifts by 0 are kept to line up the table lookups
+
+extern crate libc;
+extern crate rand;
+
+pub mod agent;
+pub mod board;
+pub mod clock;
+pub mod game;
+pub mod grid;
+pub mod heuristic;
+pub mod scoring;
+pub mod search;
+pub mod spawn;
+pub mod stats;
+
+mod generate_tables;
+
+pub use agent::{Agent, agent_by_name};
+pub use board::{Board, Direction, MoveError};
+pub use game::{Game, GridGame, Spawn};
+pub use grid::{Grid, WideBoard};
+pub use heuristic::{Heuristic, RowHeuristic, RowWeights};
+pub use search::SearchConfig;
+pub use spawn::SpawnRules;
+pub use generate_tables::init_tables;
+
+
+// Masks to extract certain information from a u64 number
+const ROW_MASK: u64 = 0xFFFF;
+const COL_MASK: u64 = 0x000F000F000F000F;
@@ -1,192 +1,584 @@
-// An implementation of a 4x4 2048 board.
-// Heavily inspired by the cpp implementation on github by user 'nneonneo'
+// Benchmark harness for the 2048 engine: plays games with the agents and summarises the results.
 extern crate rand;
+extern crate twenty48;
+
+mod cli;
+mod report;
+mod sweep;
+mod timing;
+
+use twenty48::{Agent, agent_by_name, Direction, GridGame, RowHeuristic, RowWeights, SpawnRules};
+use twenty48::agent::ExpectimaxAgent;
+use twenty48::clock::{cpu_time, CpuClock};
+use twenty48::init_tables;
+use twenty48::stats;
+use twenty48::search::{search_grid, SearchConfig, SearchStats};
+
+use cli::{Command, Format, Options};
+use report::{GameResult, Record, RecordFormat, RecordWriter};
+use sweep::Parameter;
+use timing::LatencyHistogram;
+
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::io::prelude::*;
 
-mod scoring;
-mod board;
-mod search;
+// Bootstrap: read the command line, build the tables up front and run the chosen command
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-mod generate_tables;
-use generate_tables::init_tables;
+    init_tables();
 
-use scoring::{score_board};
-use board::{get_max_rank, insert_tile_rand, draw_tile, execute_move, print_board};
-use board::{initial_board};
-use search::{find_best_move};
+    // Each board size is a different type, so the commands are built for each size the options allow
+    let result = match options.size {
+        (3, 3) => run::<3, 3>(&options),
+        (4, 5) => run::<4, 5>(&options),
+        (5, 4) => run::<5, 4>(&options),
+        (5, 5) => run::<5, 5>(&options),
+        _      => run::<4, 4>(&options),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
 
-use std::time::SystemTime;
-use std::io::prelude::*;
+// Runs the chosen command on boards W tiles wide and H high
+fn run<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    match options.command {
+        Command::Bench   => bench::<W, H>(options),
+        Command::Compare => compare::<W, H>(options),
+        Command::Play    => play::<W, H>(options),
+        Command::Analyze => analyze::<W, H>(options),
+        Command::Replay  => replay::<W, H>(options),
+        Command::Sweep   => sweep::<W, H>(options),
+        Command::Help    => {print!("{}", cli::USAGE); Ok(())}
+    }
+}
 
+// Returns the search settings for the given threshold, with the threads and budgets given on the command line
+fn search_config(options: &Options, threshold: f32) -> SearchConfig {
+    SearchConfig {prob_threshold: threshold, threads: options.threads, time_budget: options.move_time,
+                  node_budget: options.move_nodes, ..SearchConfig::default()}
+}
 
-// Tables which are filled with precomputed moves. Any row XORed with ROW_LEFT_TABLE[row] will be the result 
-// of swiping that row left, and so on with the other directions.
-static mut ROW_LEFT_TABLE:   [u16; 65536] = [0; 65536];
-static mut ROW_RIGHT_TABLE:  [u16; 65536] = [0; 65536];
-static mut COL_UP_TABLE:     [u64; 65536] = [0; 65536];
-static mut COL_DOWN_TABLE:   [u64; 65536] = [0; 65536];
+// Splits the thread budget for the given number of games into the games to play at once and the threads for
+// each of their searches. Whole games parallelise perfectly, so they get the threads first; a search has only
+// four top level moves to share out, so it never gets more than four, and a search with a node budget only
+// uses one.
+fn split_threads(options: &Options, games: usize) -> (usize, usize) {
+    let game_threads = options.game_threads.unwrap_or_else(|| options.threads.min(games)).max(1);
+    let most = if options.move_nodes.is_some() {1} else {Direction::ALL.len()};
+    let search_threads = (options.threads / game_threads).clamp(1, most);
+    (game_threads, search_threads)
+}
 
-// Precomputed heuristics and scores for single rows also
-static mut HEUR_SCORE_TABLE: [f32; 65536] = [0.0; 65536];
-static mut SCORE_TABLE:      [f32; 65536] = [0.0; 65536];
+// Plays the given number of games for each threshold and prints a summary of each. With an output file, also
+// records every game and summary in it, and with a search log the search for every move.
+fn bench<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let mut summary = String::new();
+    let tags = vec!("size".to_string());
+    let mut stdout = record_stdout(options, tags.iter().cloned().chain(report::columns(false)).collect());
+    let mut outputs = create_outputs(options, &tags)?;
+
+    let seeds = seeds(options);
+    for &threshold in &options.thresholds {
+        let (config, results) = run_config::<W, H>(options, &options.agent, threshold, &seeds, &mut outputs)?;
+        let aggregate = report::aggregate_record(&options.agent, &config, &results, options.confidence);
+        match stdout {
+            Some(ref mut stdout) => stdout.write(&size_tag::<W, H>().append(aggregate)).map_err(|e| e.to_string())?,
+            None => summary += &text_summary(threshold, &results, options.confidence),
+        }
+    }
+    outputs.finish()?;
+    if options.format == Format::Text {
+        println!("\n\n{}", summary);
+    }
+    Ok(())
+}
 
+// Plays the same games with two configurations, summarises each and tests whether their scores differ. The
+// games are paired by seed, so the test looks at the difference each configuration made to each game.
+fn compare<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let agent_b = options.agent_b.as_ref().unwrap_or(&options.agent);
+    let threshold_a = options.thresholds[0];
+    let threshold_b = *options.thresholds.get(1).unwrap_or(&threshold_a);
+    let mut outputs = create_outputs(options, &["size".to_string()])?;
+
+    let seeds = seeds(options);
+    let (config_a, results_a) = run_config::<W, H>(options, &options.agent, threshold_a, &seeds, &mut outputs)?;
+    let (config_b, results_b) = run_config::<W, H>(options, agent_b, threshold_b, &seeds, &mut outputs)?;
+    outputs.finish()?;
+
+    let comparison = stats::paired_t_test(&report::scores(&results_a), &report::scores(&results_b), options.confidence);
+    match record_stdout(options, report::comparison_columns()) {
+        Some(mut stdout) => {
+            let record = report::comparison_record((&options.agent, &config_a, &results_a), (agent_b, &config_b, &results_b), &comparison);
+            stdout.write(&record).map_err(|e| e.to_string())?;
+        }
+        None => {
+            println!();
+            println!("A: {} at {}", options.agent, threshold_a);
+            print!("{}", text_summary(threshold_a, &results_a, options.confidence));
+            println!("B: {} at {}", agent_b, threshold_b);
+            print!("{}", text_summary(threshold_b, &results_b, options.confidence));
+            println!("\nScore difference (B - A): {:.1} ({}% CI {:.1} to {:.1}) | t: {:.3} | p: {:.4} | {}",
+                     comparison.difference, options.confidence * 100.0, comparison.low, comparison.high, comparison.t,
+                     comparison.p_value,
+                     if comparison.significant {"significant"} else {"not significant"});
+        }
+    }
+    Ok(())
+}
 
-// Masks to extract certain information from a u64 number
-const ROW_MASK: u64 = 0xFFFF; 
-const COL_MASK: u64 = 0x000F000F000F000F;
+// Plays the games at every point of a sweep over search settings and heuristic weights, with the expectimax
+// agent, and prints a table of the results. Settings which are not swept come from the other options, with the
+// first threshold. Every point plays the same seeds. With an output file, also records
+// every game and every point in it, and with a search log the search for every move.
+fn sweep<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let parameters = options.params.iter().map(|spec| Parameter::parse(spec)).collect::<Result<Vec<_>, _>>()?;
+    if parameters.is_empty() {
+        return Err("sweep needs at least one --param".to_string());
+    }
+    let points = match options.samples {
+        Some(samples) => sweep::sample(&parameters, samples, options.seed),
+        None          => sweep::grid(&parameters)?,
+    };
 
-static mut CPROB_THRESH_BASE: f32 = 0.5; // Will not evaluate nodes less likely than this
+    // Parameters which are search settings are in every record already, so only the weights need adding
+    let added: Vec<&Parameter> = parameters.iter().filter(|p| !report::columns(true).contains(&p.name)).collect();
+    let mut columns = vec!("point".to_string(), "size".to_string());
+    columns.extend(added.iter().map(|parameter| parameter.name.clone()));
+    let mut outputs = create_outputs(options, &columns)?;
+    let mut stdout = record_stdout(options, columns.iter().cloned().chain(report::columns(false)).collect());
+
+    let mut table = format!("{:>5} | {} | {:>9} | {:>19} | {:>9} | {:>5} | {:>5} | {:>5} | {:>5} | {:>8} | {:>10}\n",
+                            "Point", parameters.iter().map(|p| format!("{:>12}", p.name)).collect::<Vec<_>>().join(" | "),
+                            "Score", "CI", "Median", "2k%", "4k%", "8k%", "16k%", "Moves/s", "Nodes/s");
+    let seeds = seeds(options);
+    for (i, point) in points.iter().enumerate() {
+        let mut config = search_config(options, options.thresholds[0]);
+        let mut weights = RowWeights::default();
+        let mut tags = Record::default().with("point", i + 1).append(size_tag::<W, H>());
+        for (parameter, &value) in parameters.iter().zip(point.iter()) {
+            sweep::apply(&parameter.name, value, &mut config, &mut weights)?;
+            if added.contains(&parameter) {
+                tags = tags.with(&parameter.name, value);
+            }
+        }
 
-// Bootstrap: initialise tables and play a game
-fn main() {
+        let label = format!("point {}/{}: {}", i + 1, points.len(),
+                            parameters.iter().zip(point.iter()).map(|(p, v)| format!("{}={}", p.name, v)).collect::<Vec<_>>().join(", "));
+        let (config, results) = run_games::<W, H, _>(options, &label, "expectimax", config, &seeds, &mut outputs, &tags, |config| {
+            Box::new(ExpectimaxAgent::new(config.clone(), RowHeuristic::new(weights)))
+        })?;
 
-    const RUNS: u16 = 5;
-    const TEST_VALUES: [f32; 4] = [0.01, 0.005, 0.001, 0.0005];
+        match stdout {
+            Some(ref mut stdout) => {
+                let aggregate = report::aggregate_record("expectimax", &config, &results, options.confidence);
+                stdout.write(&tags.append(aggregate)).map_err(|e| e.to_string())?;
+            }
+            None => {
+                let score = report::score_summary(&results, options.confidence);
+                table += &format!("{:>5} | {} | {:>9.0} | {:>19} | {:>9.0} | {:5.1} | {:5.1} | {:5.1} | {:5.1} | {:8.1} | {:10.0}\n",
+                                  i + 1, point.iter().map(|v| format!("{:>12}", v)).collect::<Vec<_>>().join(" | "),
+                                  score.mean, format!("{:.0}-{:.0}", score.mean_low, score.mean_high), score.median,
+                                  report::reach_rate(&results, 11), report::reach_rate(&results, 12),
+                                  report::reach_rate(&results, 13), report::reach_rate(&results, 14),
+                                  avg(results.iter().map(GameResult::move_rate)), avg(results.iter().map(GameResult::node_rate)));
+            }
+        }
+    }
+    outputs.finish()?;
+    if options.format == Format::Text {
+        println!("\n{}", table);
+    }
+    Ok(())
+}
 
-    let mut summary = String::new();
+// Returns the field naming the size of the board the games are played on, such as 4x4, which begins their records
+fn size_tag<const W: usize, const H: usize>() -> Record {
+    Record::default().with("size", format!("{}x{}", W, H).as_str())
+}
 
-    unsafe{
-        init_tables();
-    
-        for &threshold in &TEST_VALUES {
-            CPROB_THRESH_BASE = threshold;
-
-            print!("Testing {}", threshold);
-            std::io::stdout().flush();
-
-            let mut times = vec!();
-            let mut scores = vec!();
-            let mut move_rates = vec!();
-            let mut score_rates = vec!();
-            let mut max_tiles = vec!();
-
-            for run in 1..RUNS+1 {
-                
-                let (time, score, mvsec, ptsec, maxtile) = play_game(run, find_best_move); 
-
-                print!("|");
-                std::io::stdout().flush();   
-
-                times.push(time);
-                scores.push(score);
-                move_rates.push(mvsec);
-                score_rates.push(ptsec);
-                max_tiles.push(maxtile);
-            }
+// Returns the seeds of the games to play: the given number of them, counting up from the first seed
+fn seeds(options: &Options) -> Vec<u64> {
+    (0..options.games).map(|run| options.seed + run).collect()
+}
 
-            println!();
+// Plays every seed with the given agent and threshold, showing progress on stderr and recording the games and
+// their summary in the output files if there are any
+fn run_config<const W: usize, const H: usize>(options: &Options, agent: &str, threshold: f32, seeds: &[u64], outputs: &mut Outputs)
+    -> Result<(SearchConfig, Vec<GameResult>), String>
+{
+    let config = search_config(options, threshold);
+    let label = format!("{} at {}", agent, threshold);
+    run_games(options, &label, agent, config, seeds, outputs, &size_tag::<W, H>(), |config| {
+        agent_by_name::<W, H>(agent, config).expect("Unknown agent")
+    })
+}
 
-            summary += &format!("{:4.4} | Time: {:5.1} | Moves/s: {:7.2} | Points/s: {:9.2} | 2k%: {:5.1} | 4k%: {:5.1} | 8k%: {:5.1} | 16k%: {:5.1} | 32k%: {:5.1} | 64k%: {:5.1}\n",
-                    CPROB_THRESH_BASE,
-                    avg2(&times),
-                    avg(&move_rates),
-                    avg(&score_rates),
-                    percent_above(&max_tiles, 11),
-                    percent_above(&max_tiles, 12),
-                    percent_above(&max_tiles, 13),
-                    percent_above(&max_tiles, 14),
-                    percent_above(&max_tiles, 15),
-                    percent_above(&max_tiles, 16));
+// Plays every seed with agents from make_agent, giving them the search settings with the threads the budget
+// leaves for the search. Shows progress on stderr under the label, and records the games and their summary, and
+// the search for every move, in the output files if there are any, each beginning with the fields of tags.
+#[allow(clippy::too_many_arguments)]
+fn run_games<const W: usize, const H: usize, F>(options: &Options, label: &str, agent: &str, mut config: SearchConfig, seeds: &[u64],
+                outputs: &mut Outputs, tags: &Record, make_agent: F) -> Result<(SearchConfig, Vec<GameResult>), String>
+    where F: Fn(&SearchConfig) -> Box<dyn Agent<W, H> + Send> + Sync
+{
+    let (game_threads, search_threads) = split_threads(options, seeds.len());
+    config.threads = search_threads;
+
+    // Progress goes to stderr so the summary can be piped on its own
+    eprint!("Testing {}", label);
+    std::io::stderr().flush().unwrap();
+
+    // A game which has the process to itself is timed on the process clock. Otherwise its own thread's clock
+    // times it, and its searches add the time of the threads they start.
+    let clock = if game_threads == 1 {CpuClock::Process} else {CpuClock::Thread};
+    let results = play_games(seeds, game_threads, &config, clock, outputs.searches.is_some(), make_agent);
+
+    eprintln!();
+
+    if let Some(ref mut output) = outputs.records {
+        for result in &results {
+            output.write(&tags.clone().append(report::game_record(agent, &config, result))).map_err(|e| e.to_string())?;
         }
-        println!("\n\n{}", summary);
+        let aggregate = report::aggregate_record(agent, &config, &results, options.confidence);
+        output.write(&tags.clone().append(aggregate)).map_err(|e| e.to_string())?;
     }
+    if let Some(ref mut output) = outputs.searches {
+        for result in &results {
+            for (i, search) in result.searches.iter().enumerate() {
+                let record = report::search_record(agent, &config, result.seed, i + 1, search);
+                output.write(&tags.clone().append(record)).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok((config, results))
 }
 
+// Returns a writer for records on stdout in the chosen format, or None if the format is text
+fn record_stdout(options: &Options, columns: Vec<String>) -> Option<RecordWriter> {
+    match options.format {
+        Format::Text  => None,
+        Format::Csv   => Some(RecordFormat::Csv),
+        Format::Jsonl => Some(RecordFormat::Jsonl),
+    }.map(|format| RecordWriter::new(Box::new(std::io::stdout()), format, columns))
+}
 
-// Uses expectimax search to play one game of 2048 to completion
-fn play_game(run_num: u16, get_move: fn(u64) -> u8) -> (u64, f32, f32, f32, u16) {
-    let mut board: u64 = initial_board();
-    let mut moveno = 0;
-    let mut scorepenalty: u32 = 0;
-    let mut got_max_tile = false;
+// The files records are written to, for those which were asked for
+struct Outputs {
+    records: Option<RecordWriter>,   // Every game and every summary
+    searches: Option<RecordWriter>,  // The search for every move of every game
+}
 
-    let start = SystemTime::now();
-    
-    loop {
+impl Outputs {
+    fn finish(&mut self) -> Result<(), String> {
+        for output in self.records.iter_mut().chain(self.searches.iter_mut()) {
+            output.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
 
-    	//print_board(board);
+// Opens the output file and the search log, if they were given, with the given tag columns before their own
+fn create_outputs(options: &Options, tags: &[String]) -> Result<Outputs, String> {
+    let create = |path: &Option<String>, columns: Vec<String>| match *path {
+        Some(ref path) => {
+            let columns = tags.iter().cloned().chain(columns).collect();
+            RecordWriter::create(path, columns).map(Some).map_err(|e| format!("{}: {}", path, e))
+        }
+        None => Ok(None),
+    };
+    Ok(Outputs {
+        records: create(&options.output, report::columns(true))?,
+        searches: create(&options.search_log, report::search_columns())?,
+    })
+}
 
-        let mv: u8;
-        let newboard: u64;
-        let mut i = 0;
-        while i < 4 {
-            if execute_move(i, board) != board {
-                break;
-            }
-            i+=1;
+// Returns the text summary of a set of games: the original rates line, then the spread of the scores and the
+// intervals for the rates
+fn text_summary(threshold: f32, results: &[GameResult], confidence: f64) -> String {
+    let score = report::score_summary(results, confidence);
+    let interval = |rank| {
+        let (low, high) = report::reach_interval(results, rank, confidence);
+        format!("{:5.1}-{:5.1}", low, high)
+    };
+    let latency = report::merged_latency(results);
+    let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+    let cpu = report::mean_cpu_time(results).map(|cpu| format!("{:.3}", cpu)).unwrap_or_else(|| "n/a".to_string());
+    let per_move = |count: &dyn Fn(&SearchStats) -> u64| avg(results.iter().map(|r| count(&r.search) as f64 / r.moves.max(1) as f64));
+    format!("{:4.4} | Time: {:7.3} | Moves/s: {:7.2} | Points/s: {:9.2} | 2k%: {:5.1} | 4k%: {:5.1} | 8k%: {:5.1} | 16k%: {:5.1} | 32k%: {:5.1} | 64k%: {:5.1} | 128k%: {:5.1}\n",
+            threshold,
+            avg(results.iter().map(|r| r.time)),
+            avg(results.iter().map(GameResult::move_rate)),
+            avg(results.iter().map(GameResult::score_rate)),
+            report::reach_rate(results, 11),
+            report::reach_rate(results, 12),
+            report::reach_rate(results, 13),
+            report::reach_rate(results, 14),
+            report::reach_rate(results, 15),
+            report::reach_rate(results, 16),
+            report::reach_rate(results, 17)) +
+    &format!("       | Score: {:.0} ({}% CI {:.0}-{:.0}) | SD: {:.0} | Median: {:.0} | IQR: {:.0}-{:.0} | Range: {:.0}-{:.0}\n",
+             score.mean, confidence * 100.0, score.mean_low, score.mean_high, score.std_dev, score.median,
+             score.q25, score.q75, score.min, score.max) +
+    &format!("       | Reach rate intervals: 2k%: {} | 4k%: {} | 8k%: {} | 16k%: {} | 32k%: {}\n",
+             interval(11), interval(12), interval(13), interval(14), interval(15)) +
+    &format!("       | Move latency (ms): p50: {:.3} | p95: {:.3} | p99: {:.3} | max: {:.3} | CPU time (s): {}\n",
+             ms(latency.quantile(0.50)), ms(latency.quantile(0.95)), ms(latency.quantile(0.99)), ms(latency.max()), cpu) +
+    &format!("       | Nodes/move: {:.0} | Nodes/s: {:.0} | Cache hits/move: {:.0} | Carried over: {:.1}% of hits\n",
+             per_move(&|search| search.nodes), avg(results.iter().map(GameResult::node_rate)),
+             per_move(&|search| search.cache_hits),
+             100.0 * results.iter().map(|r| r.search.carried_hits).sum::<u64>() as f64
+                   / results.iter().map(|r| r.search.cache_hits).sum::<u64>().max(1) as f64)
+}
+
+// Plays one game with the first threshold and prints how it went, including the moves so it can be replayed
+fn play<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let config = search_config(options, options.thresholds[0]);
+    let mut agent = agent_by_name::<W, H>(&options.agent, &config).ok_or("Unknown agent")?;
+    let mut game = GridGame::<W, H>::with_rules(options.seed, config.rules.clone());
+
+    let start = Instant::now();
+    agent.new_game(&game);
+    while !game.is_over() {
+        let mv = match agent.choose_move(&game) {
+            Some(mv) => mv,
+            None => break,
+        };
+        if game.play(mv).is_err() {
+            return Err(format!("{} chose an illegal move ({}) on move {}", options.agent, mv, game.move_count() + 1));
+        }
+    }
+    agent.end_game(&game);
+    let time = start.elapsed().as_secs_f64();
+
+    let moves: String = game.moves().iter().map(|mv| mv.letter()).collect();
+    match options.format {
+        Format::Text => {
+            print!("{}", game.grid());
+            println!("Game Over. Seed: {}. Score: {}. Moves: {}. Highest Tile: {}. Time: {:.1}s",
+                     game.seed(), game.score(), game.move_count(), 1u64 << game.max_rank(), time);
+            println!("Moves: {}", moves);
+        }
+        Format::Csv => {
+            println!("seed,agent,threshold,score,moves,max_tile,time,move_list");
+            println!("{},{},{},{},{},{},{},{}", game.seed(), options.agent, config.prob_threshold, game.score(),
+                     game.move_count(), 1u64 << game.max_rank(), time, moves);
+        }
+        Format::Jsonl => {
+            println!("{{\"seed\":{},\"agent\":\"{}\",\"threshold\":{},\"score\":{},\"moves\":{},\"max_tile\":{},\"time\":{},\"move_list\":\"{}\"}}",
+                     game.seed(), options.agent, config.prob_threshold, game.score(), game.move_count(),
+                     1u64 << game.max_rank(), time, moves);
+        }
+    }
+    Ok(())
+}
+
+// Prints the given board, the move the search would make on it with the first threshold, the value it gave each
+// move and the work it did
+fn analyze<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let arg = options.args.first().ok_or("analyze needs a board")?;
+    let grid = cli::parse_board::<W, H>(arg)?;
+    let config = search_config(options, options.thresholds[0]);
+    let result = search_grid(grid, &config, &RowHeuristic::default());
+
+    let columns = ["board", "threshold"].iter().chain(report::SEARCH_FIELDS.iter()).map(|c| c.to_string()).collect();
+    match record_stdout(options, columns) {
+        Some(mut stdout) => {
+            // A 4x4 board is given as its bitboard, and others by their tiles
+            let board = match grid.to_bitboard() {
+                Some(board) => Record::default().with("board", board.raw()),
+                None        => Record::default().with("board", arg.as_str()),
+            };
+            let record = board
+                .with("threshold", config.prob_threshold)
+                .append(report::search_fields(&result));
+            stdout.write(&record).map_err(|e| e.to_string())?;
         }
-        if i >= 4 {
-            break;
+        None => {
+            print!("{}", grid);
+            println!("Best move: {}", result.best.map(|mv| mv.to_string()).unwrap_or_else(|| "none".to_string()));
+            println!("Values: {}", Direction::ALL.iter().map(|mv| format!("{} {:.1}", mv, result.values[mv.index()]))
+                                                 .collect::<Vec<_>>().join(" | "));
+            println!("Depth: {} (deepest line {}) | Nodes: {} | Cache hits: {} | Time: {:.1}ms",
+                     result.depth, result.max_depth, result.stats.nodes, result.stats.cache_hits,
+                     result.elapsed.as_secs_f64() * 1000.0);
         }
+    }
+    Ok(())
+}
 
-        //println!("Run {}, Mov #{}, current score={}, max_Tile={}",run_num, moveno, score_board(board) - scorepenalty as f32, 2<<(get_max_rank(board) -1));
-        //std::io::stdout().flush();
-        moveno += 1;
+// Replays the game with the given seed and moves, printing the board after each move
+fn replay<const W: usize, const H: usize>(options: &Options) -> Result<(), String> {
+    let arg = options.args.first().ok_or("replay needs a list of moves")?;
+    let moves = cli::parse_moves(arg)?;
+    let mut game = GridGame::<W, H>::with_rules(options.seed, SpawnRules::default());
 
-        mv = get_move(board);
-        if mv > 3 {
-            break;
+    if options.format == Format::Csv {
+        println!("move,direction,points,score,max_tile");
+    }
+    for (i, &mv) in moves.iter().enumerate() {
+        let points = game.play(mv).map_err(|e| format!("Move {} ({}): {}", i + 1, mv, e))?;
+        match options.format {
+            Format::Text  => print!("Move {}: {}, +{} points\n{}\n", i + 1, mv, points, game.grid()),
+            Format::Csv   => println!("{},{},{},{},{}", i + 1, mv, points, game.score(), 1u64 << game.max_rank()),
+            Format::Jsonl => println!("{{\"move\":{},\"direction\":\"{}\",\"points\":{},\"score\":{},\"max_tile\":{}}}",
+                                      i + 1, mv, points, game.score(), 1u64 << game.max_rank()),
         }
+    }
+    if options.format == Format::Text {
+        println!("Score: {}. Highest Tile: {}. Game over: {}", game.score(), 1u64 << game.max_rank(), game.is_over());
+    }
+    Ok(())
+}
 
-        newboard = execute_move(mv, board);
-        if newboard == board {
-            println!("Illegal Move");
-            moveno -= 1;
-            continue;
-        } else if score_board(newboard) < score_board(board) {
-            println!("Merged two 32k tiles, losing score in the process");
-            got_max_tile = true;
-            break;
+// Plays the games with the given seeds on a pool of workers, each with its own agent from make_agent, and returns
+// their results in seed order, keeping the agents' search for every move if log_searches is set. Every game is
+// decided by its seed, and with exact cache budgets a search's values do not depend on how its threads are
+// scheduled, so the games are the same however the threads are split and whichever worker plays each one. Only
+// a time budget, or covering cache budgets on several threads, can make them differ.
+fn play_games<const W: usize, const H: usize, F>(seeds: &[u64], workers: usize, config: &SearchConfig,
+                                                 clock: CpuClock, log_searches: bool, make_agent: F) -> Vec<GameResult>
+    where F: Fn(&SearchConfig) -> Box<dyn Agent<W, H> + Send> + Sync
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut results = vec!(None; seeds.len());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.min(seeds.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            let make_agent = &make_agent;
+            scope.spawn(move || {
+                let mut agent = make_agent(config);
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= seeds.len() {
+                        break;
+                    }
+                    let result = play_game(seeds[i], &config.rules, agent.as_mut(), clock, log_searches);
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
         }
+        drop(sender);
 
-        let tile: u64 = draw_tile();
-        if tile == 2 {scorepenalty += 4};
-        board = insert_tile_rand(newboard, tile);
-    }
+        // Games finish in any order; each goes back in its own slot
+        for (i, result) in receiver {
+            eprint!("|");
+            std::io::stderr().flush().unwrap();
+            results[i] = Some(result);
+        }
+    });
+    results.into_iter().map(|result| result.expect("Worker died before finishing its game")).collect()
+}
 
-    let diff = match SystemTime::now().duration_since(start) {
-        Ok(duration) => duration,
-        Err(duration) => {println!("Time error"); duration.duration()}
-    };
-    
-    let final_score = score_board(board) - scorepenalty as f32;
-    let time = diff.as_secs();
+// Uses the given agent to play one game of 2048 to completion. The seed and rules decide every tile spawned, so
+// the same seed, rules and agent always play the same game. Measures the CPU time spent on the given clock, and
+// keeps the agent's search for every move if log_searches is set.
+fn play_game<const W: usize, const H: usize>(seed: u64, rules: &SpawnRules, agent: &mut dyn Agent<W, H>,
+                                             clock: CpuClock, log_searches: bool) -> GameResult {
+    let mut game = GridGame::<W, H>::with_rules(seed, rules.clone());
+    agent.new_game(&game);
+
+    // The move on which the highest tile first reached each rank; the starting tiles count as move 0
+    let mut first_reached = vec!();
+    first_reached.resize(game.max_rank() as usize + 1, Some(0));
+
+    let mut latency = LatencyHistogram::default();
+    let mut searches = vec!();
+    let start = Instant::now();
+    let cpu_start = cpu_time(clock);
+
+    while !game.is_over() {
+        let move_start = Instant::now();
+        let choice = agent.choose_move(&game);
+        latency.record(move_start.elapsed());
+        if log_searches {
+            searches.extend(agent.last_search());
+        }
+        let mv = match choice {
+            Some(mv) => mv,
+            None => break,
+        };
 
-    //println!("");
-    //print_board(board);
-    //println!("Game Over. Score: {}. Highest Tile: {}.", final_score, get_max_rank(board));
+        if let Err(e) = game.play(mv) {
+            panic!("The agent chose an illegal move ({}) on move {}: {}", mv, game.move_count() + 1, e);
+        }
 
-    // Return Time, Score, Moves/s, Pts/s, Highest Tile
-    (time, final_score, moveno as f32/time as f32, final_score/time as f32, if !got_max_tile { get_max_rank(board) } else { 16 }) 
-}
+        if game.max_rank() as usize >= first_reached.len() {
+            first_reached.resize(game.max_rank() as usize + 1, Some(game.move_count()));
+        }
+    }
 
-fn avg(vec: &[f32]) -> f32 {
-    let mut res: f32 = 0.0;
-    
-    for num in vec {
-        res += *num;
+    agent.end_game(&game);
+
+    let time = start.elapsed();
+    let cpu_end = cpu_time(clock);
+    // The thread clock misses the threads the searches started
+    let search = agent.search_stats();
+    let worker_cpu = if clock == CpuClock::Thread {search.worker_cpu_time} else {Duration::ZERO};
+
+    GameResult {
+        seed,
+        score: game.score(),
+        moves: game.move_count(),
+        max_rank: game.max_rank(),
+        time: time.as_secs_f64(),
+        cpu_time: cpu_start.and_then(|start| cpu_end.map(|end| (end - start + worker_cpu).as_secs_f64())),
+        search,
+        latency,
+        first_reached,
+        searches,
     }
-    
-    res/vec.len() as f32
 }
 
-fn avg2(vec: &[u64]) -> f32 {
-    let mut res: f32 = 0.0;
-    
-    for num in vec {
-        res += *num as f32;
+fn avg<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let mut res = 0.0;
+    let mut count = 0;
+
+    for num in values {
+        res += num;
+        count += 1;
     }
-    
-    res/vec.len() as f32
+
+    res/count as f64
 }
 
-fn percent_above(vec: &[u16], thresh: u16) -> f32 {
-    let mut amnt = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for num in vec {
-        if *num >= thresh {
-            amnt += 1;
+    #[test]
+    fn the_thread_split_does_not_change_the_games() {
+        init_tables();
+        // A high threshold keeps the games short, while their searches still share many positions between threads
+        let seeds = [1, 2];
+        let play = |threads: usize, game_threads: Option<usize>| {
+            let options = Options {threads, game_threads, ..Options::default()};
+            let mut outputs = Outputs {records: None, searches: None};
+            let (_, results) = run_config::<4, 4>(&options, "expectimax", 0.2, &seeds, &mut outputs).unwrap();
+            results.iter().map(|game| (game.seed, game.score, game.moves, game.max_rank, game.first_reached.clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected = play(1, None);
+        for (threads, game_threads) in [(4, Some(1)), (4, Some(2)), (4, None)] {
+            assert_eq!(play(threads, game_threads), expected, "{} threads, {:?} game threads", threads, game_threads);
         }
     }
-
-    (amnt as f32 / vec.len() as f32) * 100.0
 }
@@ -0,0 +1,381 @@
+// Machine readable results for the harness. Each record is a flat list of named values, written either as a
+// CSV row under a fixed header or as a JSON object on its own line. Games and aggregates share one file, told
+// apart by their "record" field; CSV cells which do not apply to a record are left empty.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use twenty48::Direction;
+use twenty48::search::{SearchConfig, SearchResult, SearchStats};
+use twenty48::stats::{self, Comparison, Summary};
+
+use timing::LatencyHistogram;
+
+// The ranks whose first appearance is recorded for each game, from 2 up to 131072
+const MOVE_RANKS: ::std::ops::RangeInclusive<u16> = 1..=17;
+// The ranks whose reach rates are recorded for each aggregate, from 2048 up to 131072
+const RATE_RANKS: ::std::ops::RangeInclusive<u16> = 11..=17;
+// The fields describing a single search, in order
+pub const SEARCH_FIELDS: [&str; 11] = ["best_move", "value_up", "value_down", "value_left", "value_right", "depth",
+                                   "max_depth", "nodes", "cache_hits", "carried_hits", "time_ms"];
+
+// A single value in a record
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Int(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl Value {
+    fn to_csv(&self) -> String {
+        match *self {
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) => x.to_string(),
+            Value::Text(ref s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+            Value::Text(ref s) => s.clone(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match *self {
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) if x.is_finite() => x.to_string(),
+            Value::Float(_) => "null".to_string(),
+            Value::Text(ref s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+impl From<u64> for Value { fn from(n: u64) -> Value { Value::Int(n) } }
+impl From<usize> for Value { fn from(n: usize) -> Value { Value::Int(n as u64) } }
+impl From<f64> for Value { fn from(x: f64) -> Value { Value::Float(x) } }
+// Goes through the shortest decimal form of the f32, so that 0.01 is written as 0.01 rather than 0.009999999776
+impl From<f32> for Value { fn from(x: f32) -> Value { Value::Float(x.to_string().parse().unwrap_or(x as f64)) } }
+impl<'a> From<&'a str> for Value { fn from(s: &'a str) -> Value { Value::Text(s.to_string()) } }
+
+// A named list of values, in the order they are written
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Record {
+    fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    // Returns this record with the given field added at the end
+    pub fn with<V: Into<Value>>(mut self, name: &str, value: V) -> Record {
+        self.fields.push((name.to_string(), value.into()));
+        self
+    }
+
+    // Returns this record with the other record's fields added at the end
+    pub fn append(mut self, other: Record) -> Record {
+        self.fields.extend(other.fields);
+        self
+    }
+
+    // Returns the value of the named field, if the record has it
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|field| field.0 == name).map(|field| &field.1)
+    }
+}
+
+// The formats records can be written in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFormat {
+    Csv,
+    Jsonl,
+}
+
+impl RecordFormat {
+    // Returns the format for a file with the given name: CSV for .csv files, otherwise JSON Lines
+    pub fn for_path(path: &str) -> RecordFormat {
+        if path.to_lowercase().ends_with(".csv") {RecordFormat::Csv} else {RecordFormat::Jsonl}
+    }
+}
+
+// Writes records out in one of the formats. For CSV the header is the given columns, written before the
+// first record.
+pub struct RecordWriter {
+    out: Box<dyn Write>,
+    format: RecordFormat,
+    columns: Vec<String>,
+    started: bool,
+}
+
+impl RecordWriter {
+    pub fn new(out: Box<dyn Write>, format: RecordFormat, columns: Vec<String>) -> RecordWriter {
+        RecordWriter { out, format, columns, started: false }
+    }
+
+    // Creates the named file and writes to it in the format its name asks for
+    pub fn create(path: &str, columns: Vec<String>) -> io::Result<RecordWriter> {
+        let file = File::create(path)?;
+        Ok(RecordWriter::new(Box::new(BufWriter::new(file)), RecordFormat::for_path(path), columns))
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            RecordFormat::Csv => {
+                if !self.started {
+                    writeln!(self.out, "{}", self.columns.join(","))?;
+                }
+                let cells: Vec<String> = self.columns.iter()
+                    .map(|column| record.get(column).map(Value::to_csv).unwrap_or_default())
+                    .collect();
+                writeln!(self.out, "{}", cells.join(","))?;
+            }
+            RecordFormat::Jsonl => {
+                let fields: Vec<String> = record.fields.iter()
+                    .map(|(name, value)| format!("\"{}\":{}", name, value.to_json()))
+                    .collect();
+                writeln!(self.out, "{{{}}}", fields.join(","))?;
+            }
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// How a single game went
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameResult {
+    pub seed: u64,
+    pub score: u64,
+    pub moves: usize,
+    pub max_rank: u16,
+    pub time: f64,                          // Seconds spent playing the game
+    pub cpu_time: Option<f64>,              // CPU seconds spent playing the game, if the platform can tell
+    pub search: SearchStats,                // What the agent's searches did over the game
+    pub latency: LatencyHistogram,          // How long the agent took to choose each move
+    pub first_reached: Vec<Option<usize>>,  // For each rank, the move on which the highest tile first reached it
+    pub searches: Vec<SearchResult>,        // The agent's search for each move, if they were kept
+}
+
+impl GameResult {
+    // Returns the moves played per second
+    pub fn move_rate(&self) -> f64 {
+        self.moves as f64 / self.time
+    }
+
+    // Returns the points scored per second
+    pub fn score_rate(&self) -> f64 {
+        self.score as f64 / self.time
+    }
+
+    // Returns the game states searched per second
+    pub fn node_rate(&self) -> f64 {
+        self.search.nodes as f64 / self.time
+    }
+}
+
+// Returns every column an aggregate record can have, and a game record too if games is set, in order
+pub fn columns(games: bool) -> Vec<String> {
+    let mut columns: Vec<String> = ["record", "agent", "threshold", "cache_depth_limit", "min_depth", "depth_offset",
+                                    "move_time_ms", "move_nodes", "seed", "games", "score", "moves", "nodes",
+                                    "cache_hits", "carried_hits", "max_tile", "time", "cpu_time", "moves_per_sec",
+                                    "points_per_sec", "nodes_per_sec",
+                                    "latency_mean_ms", "latency_p50_ms", "latency_p95_ms", "latency_p99_ms", "latency_max_ms"]
+        .iter().map(|column| column.to_string()).collect();
+    columns.extend(["score_std_dev", "score_median", "score_q25", "score_q75", "score_ci_low", "score_ci_high"]
+        .iter().map(|column| column.to_string()));
+    if games {
+        columns.extend(MOVE_RANKS.map(|rank| format!("move_{}", 1u64 << rank)));
+    }
+    for rank in RATE_RANKS {
+        columns.push(format!("rate_{}", 1u64 << rank));
+        columns.push(format!("rate_{}_low", 1u64 << rank));
+        columns.push(format!("rate_{}_high", 1u64 << rank));
+    }
+    columns
+}
+
+// Returns every column of a comparison record, in order
+pub fn comparison_columns() -> Vec<String> {
+    ["record", "agent_a", "threshold_a", "agent_b", "threshold_b", "games", "score_a", "score_b", "difference",
+     "difference_low", "difference_high", "t", "p_value", "significant"]
+        .iter().map(|column| column.to_string()).collect()
+}
+
+// Returns every column of a search record, in order
+pub fn search_columns() -> Vec<String> {
+    ["record", "agent", "threshold", "cache_depth_limit", "min_depth", "depth_offset", "move_time_ms", "move_nodes",
+     "seed", "move"].iter().chain(SEARCH_FIELDS.iter()).map(|column| column.to_string()).collect()
+}
+
+// Returns the fields describing the agent and its settings, which begin every record
+fn config_record(kind: &str, agent: &str, config: &SearchConfig) -> Record {
+    let mut record = Record::default()
+        .with("record", kind)
+        .with("agent", agent)
+        .with("threshold", config.prob_threshold)
+        .with("cache_depth_limit", config.cache_depth_limit as u64)
+        .with("min_depth", config.min_depth as u64)
+        .with("depth_offset", config.depth_offset as u64);
+    if let Some(time) = config.time_budget {
+        record = record.with("move_time_ms", time.as_secs_f64() * 1000.0);
+    }
+    if let Some(nodes) = config.node_budget {
+        record = record.with("move_nodes", nodes);
+    }
+    record
+}
+
+// Returns the record of a single game
+pub fn game_record(agent: &str, config: &SearchConfig, game: &GameResult) -> Record {
+    let mut record = config_record("game", agent, config)
+        .with("seed", game.seed)
+        .with("score", game.score)
+        .with("moves", game.moves)
+        .with("nodes", game.search.nodes)
+        .with("cache_hits", game.search.cache_hits)
+        .with("carried_hits", game.search.carried_hits)
+        .with("max_tile", 1u64 << game.max_rank)
+        .with("time", game.time);
+    if let Some(cpu_time) = game.cpu_time {
+        record = record.with("cpu_time", cpu_time);
+    }
+    record = latency_fields(record
+        .with("moves_per_sec", game.move_rate())
+        .with("points_per_sec", game.score_rate())
+        .with("nodes_per_sec", game.node_rate()), &game.latency);
+    for rank in MOVE_RANKS {
+        if let Some(&Some(mv)) = game.first_reached.get(rank as usize) {
+            record = record.with(&format!("move_{}", 1u64 << rank), mv);
+        }
+    }
+    record
+}
+
+// Returns the record of the search for the given move of the game with the given seed
+pub fn search_record(agent: &str, config: &SearchConfig, seed: u64, move_number: usize, search: &SearchResult) -> Record {
+    config_record("search", agent, config)
+        .with("seed", seed)
+        .with("move", move_number)
+        .append(search_fields(search))
+}
+
+// Returns the fields describing a single search: the move it chose, the value of every move and the work it did
+pub fn search_fields(search: &SearchResult) -> Record {
+    let best = search.best.map(|mv| mv.to_string()).unwrap_or_else(|| "none".to_string());
+    let mut record = Record::default().with("best_move", best.as_str());
+    for mv in Direction::ALL {
+        record = record.with(&format!("value_{}", mv), search.values[mv.index()]);
+    }
+    record
+        .with("depth", search.depth as u64)
+        .with("max_depth", search.max_depth as u64)
+        .with("nodes", search.stats.nodes)
+        .with("cache_hits", search.stats.cache_hits)
+        .with("carried_hits", search.stats.carried_hits)
+        .with("time_ms", search.elapsed.as_secs_f64() * 1000.0)
+}
+
+// Returns the record summarising a set of games played with the same agent and settings: the means of their
+// results and the best tile, the spread of their scores, and the percentage of games reaching each of the big
+// tiles. Intervals are given at the given confidence.
+pub fn aggregate_record(agent: &str, config: &SearchConfig, games: &[GameResult], confidence: f64) -> Record {
+    let mean = |f: &dyn Fn(&GameResult) -> f64| stats::mean(&games.iter().map(f).collect::<Vec<_>>());
+    let score = score_summary(games, confidence);
+    let mut record = config_record("aggregate", agent, config)
+        .with("seed", games.iter().map(|game| game.seed).min().unwrap_or(0))
+        .with("games", games.len())
+        .with("score", score.mean)
+        .with("moves", mean(&|game| game.moves as f64))
+        .with("nodes", mean(&|game| game.search.nodes as f64))
+        .with("cache_hits", mean(&|game| game.search.cache_hits as f64))
+        .with("carried_hits", mean(&|game| game.search.carried_hits as f64))
+        .with("max_tile", 1u64 << games.iter().map(|game| game.max_rank).max().unwrap_or(0))
+        .with("time", mean(&|game| game.time));
+    if let Some(cpu_time) = mean_cpu_time(games) {
+        record = record.with("cpu_time", cpu_time);
+    }
+    record = latency_fields(record
+        .with("moves_per_sec", mean(&GameResult::move_rate))
+        .with("points_per_sec", mean(&GameResult::score_rate))
+        .with("nodes_per_sec", mean(&GameResult::node_rate)), &merged_latency(games))
+        .with("score_std_dev", score.std_dev)
+        .with("score_median", score.median)
+        .with("score_q25", score.q25)
+        .with("score_q75", score.q75)
+        .with("score_ci_low", score.mean_low)
+        .with("score_ci_high", score.mean_high);
+    for rank in RATE_RANKS {
+        let (low, high) = reach_interval(games, rank, confidence);
+        record = record
+            .with(&format!("rate_{}", 1u64 << rank), reach_rate(games, rank))
+            .with(&format!("rate_{}_low", 1u64 << rank), low)
+            .with(&format!("rate_{}_high", 1u64 << rank), high);
+    }
+    record
+}
+
+// Returns the record with the latency percentiles of the histogram added, in milliseconds
+fn latency_fields(record: Record, latency: &LatencyHistogram) -> Record {
+    record
+        .with("latency_mean_ms", latency.mean().as_secs_f64() * 1000.0)
+        .with("latency_p50_ms", latency.quantile(0.50).as_secs_f64() * 1000.0)
+        .with("latency_p95_ms", latency.quantile(0.95).as_secs_f64() * 1000.0)
+        .with("latency_p99_ms", latency.quantile(0.99).as_secs_f64() * 1000.0)
+        .with("latency_max_ms", latency.max().as_secs_f64() * 1000.0)
+}
+
+// Returns the latencies of every move of every game in one histogram
+pub fn merged_latency(games: &[GameResult]) -> LatencyHistogram {
+    let mut latency = LatencyHistogram::default();
+    for game in games {
+        latency.merge(&game.latency);
+    }
+    latency
+}
+
+// Returns the mean CPU time of the games, or None if it could not be measured for them
+pub fn mean_cpu_time(games: &[GameResult]) -> Option<f64> {
+    let times: Option<Vec<f64>> = games.iter().map(|game| game.cpu_time).collect();
+    times.filter(|times| !times.is_empty()).map(|times| stats::mean(&times))
+}
+
+// Returns the record of a comparison between games played with the same seeds under two configurations
+pub fn comparison_record(a: (&str, &SearchConfig, &[GameResult]), b: (&str, &SearchConfig, &[GameResult]),
+                         comparison: &Comparison) -> Record {
+    Record::default()
+        .with("record", "comparison")
+        .with("agent_a", a.0)
+        .with("threshold_a", a.1.prob_threshold)
+        .with("agent_b", b.0)
+        .with("threshold_b", b.1.prob_threshold)
+        .with("games", a.2.len())
+        .with("score_a", stats::mean(&scores(a.2)))
+        .with("score_b", stats::mean(&scores(b.2)))
+        .with("difference", comparison.difference)
+        .with("difference_low", comparison.low)
+        .with("difference_high", comparison.high)
+        .with("t", comparison.t)
+        .with("p_value", comparison.p_value)
+        .with("significant", if comparison.significant {"yes"} else {"no"})
+}
+
+// Returns the final scores of the games
+pub fn scores(games: &[GameResult]) -> Vec<f64> {
+    games.iter().map(|game| game.score as f64).collect()
+}
+
+// Returns a summary of the final scores of the games, with the interval for the mean at the given confidence
+pub fn score_summary(games: &[GameResult], confidence: f64) -> Summary {
+    Summary::of(&scores(games), confidence)
+}
+
+// Returns the Wilson interval, as percentages, for the rate at which games reach the given rank
+pub fn reach_interval(games: &[GameResult], rank: u16, confidence: f64) -> (f64, f64) {
+    let reached = games.iter().filter(|game| game.max_rank >= rank).count();
+    let (low, high) = stats::wilson_interval(reached, games.len(), confidence);
+    (low * 100.0, high * 100.0)
+}
+
+// Returns the percentage of the games whose highest tile reached the given rank
+pub fn reach_rate(games: &[GameResult], rank: u16) -> f64 {
+    let reached = games.iter().filter(|game| game.max_rank >= rank).count();
+    (reached as f64 / games.len() as f64) * 100.0
+}
@@ -1,74 +1,680 @@
 use std::cmp::max;
-use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::mem::size_of;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use super::board::{execute_move};
-use super::board::{count_empty, count_distinct_tiles};
-use super::scoring::{score_heur_board};
+use board::{Board, Direction};
+use clock::{cpu_time, CpuClock};
+use board::{execute_move, legal_moves, count_empty, count_distinct_tiles};
+use grid::Grid;
+use heuristic::Heuristic;
+use spawn::SpawnRules;
 
-use super::CPROB_THRESH_BASE; // Will not evaluate nodes less likely than this
-const CACHE_DEPTH_LIMIT: u32 = 15;     // Will not cache nodes deeper than this
+// The rank of a 32768. The bitboard tables cannot merge two of them, so boards holding one are searched as grids.
+const MAX_BITBOARD_RANK: u16 = 15;
 
-type TransTable = HashMap<u64, TransTableEntry>; // Typedef to remove generics from the main code
+// Settings for the expectimax search. Each search reads its own copy, so differently configured searches can
+// run side by side.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchConfig {
+    pub prob_threshold: f32,     // Will not evaluate nodes less likely than this
+    pub cache_depth_limit: u32,  // Will not cache nodes deeper than this
+    pub min_depth: u32,          // The depth limit is never less than this...
+    pub depth_offset: u32,       // ...and is otherwise the number of distinct tiles on the board less this
+    pub rules: SpawnRules,       // The rules the game spawns tiles by, which weigh the chance nodes
+    pub threads: usize,          // The most threads to search the top level moves with
+    pub shared_table: bool,      // Whether the top level moves share one cache, rather than each having its own.
+                                 // Sharing searches about 8% fewer nodes, and with exact_budgets plays the same.
+    pub cache_generations: u32,  // How many later moves a game's cache keeps an entry for, up to 1023
+    pub cache_mb: usize,         // The memory a game's cache takes up, in megabytes
+    pub search_cache_mb: usize,  // The memory, in megabytes, of a cache which only lasts for one search: the one
+                                 // made for a search without a game's cache, or each top level move's own one
+                                 // when they do not share one. These need far less room than a game's cache.
+    pub canonical_keys: bool,    // Whether boards share cache entries with their rotations and reflections
+    pub exact_budgets: bool,     // Whether cache entries are only used for nodes with exactly their budget, which
+                                 // keeps the values the same whatever order the nodes are visited in. Otherwise
+                                 // the search is quicker, but its values depend on how threads are scheduled.
+    // With either budget set, the search deepens one level at a time until the budget runs out, instead of
+    // going straight to depth_limit, and plays the best move of the deepest search it finished
+    pub time_budget: Option<Duration>, // The most time to spend on each move
+    pub node_budget: Option<u64>,      // The most nodes to search for each move, searched on one thread
+}
+
+impl SearchConfig {
+    // Returns the maximum depth to search from the given board
+    pub fn depth_limit<P: Position>(&self, board: P) -> u32 {
+        max(self.min_depth, board.count_distinct_tiles().saturating_sub(self.depth_offset))
+    }
+
+    // Returns the most threads to search the top level moves with. Threads share a node budget and the cache, so
+    // which search uses up the budget first depends on how they were scheduled. With a node budget the search
+    // keeps to one thread, so the same board always gets the same move.
+    pub fn search_threads(&self) -> usize {
+        if self.node_budget.is_some() {1} else {self.threads.max(1)}
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            prob_threshold: 0.01,
+            cache_depth_limit: 15,
+            min_depth: 3,
+            depth_offset: 2,
+            rules: SpawnRules::default(),
+            threads: 4,
+            shared_table: true,
+            cache_generations: 1,
+            cache_mb: 16,
+            search_cache_mb: 1,
+            canonical_keys: false,
+            exact_budgets: true,
+            time_budget: None,
+            node_budget: None,
+        }
+    }
+}
+
+// A board the expectimax search can play on. The 4x4 bitboard is the fast path; Grid covers every other size.
+pub trait Position: Copy + Eq + Hash + Send + 'static {
+    // Returns the number of cells on the board
+    fn cells(&self) -> usize;
+    // Returns the result of the specified move, which is the board unchanged if nothing moves
+    fn execute_move(&self, dir: Direction) -> Self;
+    // Returns a mask of the moves which change the board. The bit for each direction is dir.mask().
+    fn legal_moves(&self) -> u8;
+    // Returns the number of open spaces on the board
+    fn count_empty(&self) -> u64;
+    // Returns the number of unique tiles on the board
+    fn count_distinct_tiles(&self) -> u32;
+    // Returns the score of the board under the given heuristic
+    fn heuristic<E: Heuristic + ?Sized>(&self, heuristic: &E) -> f32;
+    // Returns true if the given cell has no tile in it
+    fn is_empty_cell(&self, cell: usize) -> bool;
+    // Returns the board with a tile of the given rank placed in the given empty cell
+    fn with_tile(&self, cell: usize, rank: u8) -> Self;
+    // Returns a board with no tiles
+    fn empty() -> Self;
+    // Returns a hash of the board for the cache, whose every bit depends on every tile
+    fn table_hash(&self) -> u64;
+    // Returns the board the cache keeps this one's entries under when config.canonical_keys is set. Any
+    // rotation or reflection of the board must give the same one, or this board itself.
+    fn canonical(&self) -> Self { *self }
+}
+
+impl Position for Board {
+    fn cells(&self) -> usize { 16 }
+    fn execute_move(&self, dir: Direction) -> Board { Board(execute_move(dir, self.0)) }
+    fn legal_moves(&self) -> u8 { legal_moves(self.0) }
+    fn count_empty(&self) -> u64 { count_empty(self.0) }
+    fn count_distinct_tiles(&self) -> u32 { count_distinct_tiles(self.0) }
+    fn heuristic<E: Heuristic + ?Sized>(&self, heuristic: &E) -> f32 { heuristic.score_board(self.0) }
+    fn is_empty_cell(&self, cell: usize) -> bool { (self.0 >> (4 * cell)) & 0xF == 0 }
+    fn with_tile(&self, cell: usize, rank: u8) -> Board { Board(self.0 | (rank as u64) << (4 * cell)) }
+    fn empty() -> Board { Board(0) }
+    fn canonical(&self) -> Board { Board::canonical(*self) }
+    // The finaliser of 64 bit MurmurHash3, which mixes every bit of the bitboard into every bit of the hash.
+    // Boards in a search differ in a few nibbles, so the bits must be spread before they pick a bucket.
+    fn table_hash(&self) -> u64 {
+        let mut hash = self.0;
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash = (hash ^ (hash >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^ (hash >> 33)
+    }
+}
+
+impl<const W: usize, const H: usize> Position for Grid<W, H> {
+    fn cells(&self) -> usize { Self::CELLS }
+    fn execute_move(&self, dir: Direction) -> Grid<W, H> { Grid::execute_move(self, dir).0 }
+    fn legal_moves(&self) -> u8 { Grid::legal_moves(self) }
+    fn count_empty(&self) -> u64 { Grid::count_empty(self) }
+    fn count_distinct_tiles(&self) -> u32 { Grid::count_distinct_tiles(self) }
+    fn heuristic<E: Heuristic + ?Sized>(&self, heuristic: &E) -> f32 { Grid::heuristic(self, heuristic) }
+    fn is_empty_cell(&self, cell: usize) -> bool { self.rank(cell) == 0 }
+    fn with_tile(&self, cell: usize, rank: u8) -> Grid<W, H> { self.with_rank(cell, rank) }
+    fn empty() -> Grid<W, H> { Grid::empty() }
+    fn table_hash(&self) -> u64 {
+        let mut hasher = GridHasher::default();
+        self.hash(&mut hasher);
+        Board(hasher.finish()).table_hash()
+    }
+}
+
+// A fast hasher for grids. Multiplies each word in, as FxHash does.
+#[derive(Default)]
+struct GridHasher(u64);
+
+impl Hasher for GridHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// The number of entries in each bucket of the cache. A board can only be stored in the bucket its hash picks.
+const BUCKET_SLOTS: usize = 4;
+// The number of separately locked shards the cache is split into, so threads sharing it rarely wait on each other
+const TABLE_SHARDS: usize = 64;
+
+// The most generations old an entry of a game's cache can be and still be used, however many the config asks for
+const MAX_CACHE_AGE: u16 = 1023;
+
+// A place for an entry in the cache. Empty while its entry has the empty budget.
+#[derive(Clone, Copy)]
+struct Slot<P> {
+    board: P,
+    entry: TransTableEntry,
+}
+
+struct Shard<P> {
+    buckets: Vec<[Slot<P>; BUCKET_SLOTS]>,
+    counts: Vec<u64>, // The number of live entries in the shard from each generation, at the generation's count_index
+}
+
+// The game cache: a fixed number of buckets, a power of two, split between shards which are each locked
+// separately. When a bucket is full, the entry with the least depth left below it makes way, so the entries
+// which saved the most work are kept.
+struct TransTable<P> {
+    shards: Vec<Mutex<Shard<P>>>,
+    bucket_mask: u64,  // Picks a bucket within a shard from the hash
+    generation: u16,   // The generation new entries belong to
+    max_age: u16,      // How many generations old an entry may be and still be used
+    count_mask: usize, // Picks a generation's place in the shards' counts
+}
+
+impl<P: Position> TransTable<P> {
+    // Makes a cache of the given number of shards, taking up as many bytes as it can without going over the
+    // given number of megabytes, but at least one bucket for each shard
+    fn new(megabytes: usize, shards: usize) -> TransTable<P> {
+        let empty = Slot {board: P::empty(), entry: TransTableEntry {budget: NodeBudget::EMPTY, generation: 0, heuristic: 0.0}};
+        let buckets = ((megabytes << 20) / size_of::<[Slot<P>; BUCKET_SLOTS]>() / shards).max(1);
+        let buckets = 1 << (usize::BITS - 1 - buckets.leading_zeros()); // Round down to a power of two
+        TransTable {
+            shards: (0..shards).map(|_| Mutex::new(Shard {buckets: vec!([empty; BUCKET_SLOTS]; buckets), counts: vec!(0)})).collect(),
+            bucket_mask: buckets as u64 - 1,
+            generation: 0,
+            max_age: 0,
+            count_mask: 0,
+        }
+    }
+
+    // Returns the place of the generation's count in the shards' counts. There is a place for every generation
+    // young enough to use, and the number of places is a power of two so the generations keep their places when
+    // they wrap around.
+    fn count_index(&self, generation: u16) -> usize {
+        generation as usize & self.count_mask
+    }
+
+    // Returns the shard and bucket the board belongs in. The shard is picked by the high bits of the hash and
+    // the bucket by the low ones.
+    fn locate(&self, board: &P) -> (&Mutex<Shard<P>>, usize) {
+        let hash = board.table_hash();
+        (&self.shards[((hash >> 32) % self.shards.len() as u64) as usize], (hash & self.bucket_mask) as usize)
+    }
+
+    // Returns true if the entry is not empty and young enough to use
+    fn is_live(&self, entry: &TransTableEntry) -> bool {
+        entry.budget != NodeBudget::EMPTY && self.generation.wrapping_sub(entry.generation) <= self.max_age
+    }
+
+    // Returns the entry for the board searched with the biggest budget that covers the given one, or with exactly
+    // the given budget if exact is set, if there is one
+    fn get(&self, board: &P, budget: NodeBudget, exact: bool) -> Option<TransTableEntry> {
+        let (shard, bucket) = self.locate(board);
+        let shard = shard.lock().unwrap();
+        shard.buckets[bucket].iter()
+            .filter(|slot| slot.board == *board && self.is_live(&slot.entry))
+            .filter(|slot| if exact {slot.entry.budget == budget} else {slot.entry.budget.covers(budget)})
+            .max_by_key(|slot| slot.entry.budget.size())
+            .map(|slot| slot.entry)
+    }
+
+    // Stores the entry in place of the board's old one with the same budget if it has one, or unless exact is set,
+    // one with a budget the entry's covers. Otherwise it goes in an empty slot of its bucket, or one whose entry is
+    // too old to use, or in place of the entry with the smallest budget. An entry never replaces a live one with a
+    // bigger budget.
+    fn insert(&self, board: P, entry: TransTableEntry, exact: bool) {
+        let (shard, bucket) = self.locate(&board);
+        let mut shard = shard.lock().unwrap();
+        let shard = &mut *shard;
+        let slots = &mut shard.buckets[bucket];
 
-// An entry in the game cache. Stores a heuristic value and the depth at which it was computed
+        let replaces = |old: NodeBudget| if exact {old == entry.budget} else {entry.budget.covers(old)};
+        let victim = match slots.iter().position(|slot| slot.board == board && replaces(slot.entry.budget)) {
+            Some(own) => own,
+            None      => (0..BUCKET_SLOTS).min_by_key(|&i| {
+                if self.is_live(&slots[i].entry) {(1, slots[i].entry.budget.size())} else {(0, 0)}
+            }).unwrap(),
+        };
+        let old = slots[victim].entry;
+        if self.is_live(&old) && old.budget.size() > entry.budget.size() {
+            return;
+        }
+        if self.is_live(&old) {
+            shard.counts[self.count_index(old.generation)] -= 1;
+        }
+        slots[victim] = Slot {board, entry};
+        shard.counts[self.count_index(entry.generation)] += 1;
+    }
+
+    // Starts the next generation, after which entries more than max_age generations old are no longer used, and
+    // returns how many entries are left from the earlier generations
+    fn next_generation(&mut self, max_age: u32) -> u64 {
+        self.generation = self.generation.wrapping_add(1);
+        let max_age = max_age.min(MAX_CACHE_AGE as u32) as u16;
+        if max_age != self.max_age {
+            self.recount(max_age);
+        }
+
+        // The place of the new generation last held a generation too old to use
+        let (generation, index) = (self.generation, self.count_index(self.generation));
+        let places: Vec<usize> = (1..=max_age).map(|age| self.count_index(generation.wrapping_sub(age))).collect();
+        self.shards.iter_mut().map(|shard| {
+            let counts = &mut shard.get_mut().unwrap().counts;
+            counts[index] = 0;
+            places.iter().map(|&place| counts[place]).sum::<u64>()
+        }).sum()
+    }
+
+    // Changes how many generations old an entry may be, counting the live entries again if there are any
+    fn recount(&mut self, max_age: u16) {
+        self.max_age = max_age;
+        self.count_mask = (max_age as usize + 1).next_power_of_two() - 1;
+        let (generation, mask) = (self.generation, self.count_mask);
+        for shard in &mut self.shards {
+            let shard = shard.get_mut().unwrap();
+            let mut counts = vec!(0; mask + 1);
+            if shard.counts.iter().any(|&count| count > 0) {
+                for entry in shard.buckets.iter().flatten().map(|slot| slot.entry) {
+                    if entry.budget != NodeBudget::EMPTY && generation.wrapping_sub(entry.generation) <= max_age {
+                        counts[entry.generation as usize & mask] += 1;
+                    }
+                }
+            }
+            shard.counts = counts;
+        }
+    }
+}
+
+// An entry in the game cache. Stores a heuristic value, the budget it was searched with, and the generation of
+// the search which computed it.
+#[derive(Clone, Copy)]
 struct TransTableEntry {
-    depth: u8,
+    budget: NodeBudget,
+    generation: u16,
     heuristic: f32
 }
 
+// The number of steps the probability of a cached node is rounded down to in each halving
+const PROBABILITY_STEPS: f32 = 4.0;
+
+// How much searching is left below a node: the levels left before the depth limit, and its probability, which
+// the threshold cuts off. With config.exact_budgets, an entry is only used for a node with exactly its budget, so
+// as the value of a cached node depends on nothing but its board and its budget, it is the same whichever path
+// reached it first. Otherwise an entry is used for any node whose budget its own covers, so a value is never
+// reused where a deeper search is needed, but may be deeper than the node would have searched. Keeping the depth left rather than the depth from the root means an entry is
+// worth the same to the searches for later moves, whose roots are further on. To give budgets a chance of
+// matching, a cached node rounds its probability down to a whole number of steps and searches with that.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct NodeBudget {
+    remaining: u8,    // The levels left to search
+    probability: u8,  // The probability of the node, as the number of steps below 1
+}
+
+impl NodeBudget {
+    // The budget of an empty slot, which no node has, as nodes with no levels left are not cached
+    const EMPTY: NodeBudget = NodeBudget {remaining: 0, probability: 0};
+
+    // Returns the budget of a node with the given levels left and probability
+    fn new(remaining: u32, cprob: f32) -> NodeBudget {
+        let steps = (-PROBABILITY_STEPS * cprob.log2()).ceil().clamp(0.0, u8::MAX as f32);
+        NodeBudget {remaining: remaining.min(u8::MAX as u32) as u8, probability: steps as u8}
+    }
+
+    // Returns the probability the node is searched with: its own, rounded down to the step
+    fn probability(&self) -> f32 {
+        (-(self.probability as f32) / PROBABILITY_STEPS).exp2()
+    }
+
+    // Returns true if this budget searches at least as far as the other below every node: it has as many levels
+    // left or more, and as high a probability or higher
+    fn covers(&self, other: NodeBudget) -> bool {
+        self.remaining >= other.remaining && self.probability <= other.probability
+    }
+
+    // Returns a measure of how much searching the budget buys, for choosing which entries to keep: more levels
+    // first, then a higher probability
+    fn size(&self) -> u16 {
+        (self.remaining as u16) << 8 | (u8::MAX - self.probability) as u16
+    }
+}
+
+// A cache kept across the moves of a game, so the positions searched for one move can be reused for the next.
+// It takes up config.cache_mb megabytes. Each search is a new generation, and entries are no longer used once
+// they are more than config.cache_generations generations old.
+pub struct SearchCache<P: Position> {
+    table: TransTable<P>,
+}
+
+impl<P: Position> SearchCache<P> {
+    pub fn new(config: &SearchConfig) -> SearchCache<P> {
+        SearchCache { table: TransTable::new(config.cache_mb, TABLE_SHARDS) }
+    }
+
+    // Returns a cache for a single search, of config.search_cache_mb megabytes
+    fn one_shot(config: &SearchConfig) -> SearchCache<P> {
+        SearchCache { table: TransTable::new(config.search_cache_mb, TABLE_SHARDS) }
+    }
+}
+
+// The caches for a game on a board of any size. A 4x4 game is searched as bitboards until it has a 32768, whose
+// merges the bitboard tables cannot show, and as a grid after, so each cache is only made once it is needed.
+#[derive(Default)]
+pub struct GridSearchCache<const W: usize, const H: usize> {
+    board: Option<SearchCache<Board>>,
+    grid: Option<SearchCache<Grid<W, H>>>,
+}
+
+impl<const W: usize, const H: usize> GridSearchCache<W, H> {
+    pub fn new() -> GridSearchCache<W, H> {
+        GridSearchCache { board: None, grid: None }
+    }
+}
+
+// What a search did, for benchmarking
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct SearchStats {
+    pub nodes: u64,       // Game states evaluated, over every depth an iterative search tried
+    pub cache_hits: u64,  // Times a cached value was reused
+    pub carried_entries: u64, // Entries kept in a game's cache from the searches for earlier moves
+    pub carried_hits: u64,    // Times one of those was reused
+    pub worker_cpu_time: Duration, // CPU time of the threads the search started, which the caller's clock misses
+}
+
+// Everything a search found and did for one move
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchResult {
+    pub best: Option<Direction>,  // The move to make, or None if no move changes the board
+    pub values: [f32; 4],         // The value of each move, in the order of Direction::ALL; 0 if it changes nothing
+    pub depth: u32,               // The depth limit of the search the values came from
+    pub max_depth: u32,           // The deepest any line of that search went
+    pub stats: SearchStats,       // The work done, over every depth an iterative search tried
+    pub elapsed: Duration,        // The time the whole search took
+}
+
+impl SearchStats {
+    // Adds the other's counts to these
+    pub fn add(&mut self, other: SearchStats) {
+        self.nodes += other.nodes;
+        self.cache_hits += other.cache_hits;
+        self.carried_entries += other.carried_entries;
+        self.carried_hits += other.carried_hits;
+        self.worker_cpu_time += other.worker_cpu_time;
+    }
+}
+
+// The deepest an iteratively deepened search will go, however much budget it has left
+const MAX_ITERATIVE_DEPTH: u32 = 64;
+// The number of nodes each thread searches between looks at the budget
+const BUDGET_CHECK_INTERVAL: u64 = 1024;
+
+// The time and nodes an iteratively deepened search may use for one move, shared by all of its threads
+struct Budget {
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    nodes: AtomicU64,       // Nodes searched so far, counted a check interval at a time and when each evaluation ends
+    exhausted: AtomicBool,  // Set once either limit has been passed
+}
+
+impl Budget {
+    fn new(config: &SearchConfig) -> Budget {
+        Budget {
+            deadline: config.time_budget.map(|time| Instant::now() + time),
+            node_limit: config.node_budget,
+            nodes: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    // Counts the given nodes as searched, and returns true if the budget has run out
+    fn spend(&self, nodes: u64) -> bool {
+        let total = self.nodes.fetch_add(nodes, Ordering::Relaxed) + nodes;
+        if self.node_limit.is_some_and(|limit| total >= limit) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+        self.is_exhausted()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
+
 // The state of the current evaluation
-struct EvalState {
-    trans_table: TransTable, // The cache for this evaluation
+struct EvalState<'a, P: Position, E: Heuristic + ?Sized> {
+    config: &'a SearchConfig,   // The settings for this evaluation
+    heuristic: &'a E,           // Scores the leaves of this evaluation
+    trans_table: &'a TransTable<P>, // The cache for this evaluation, which may be shared with other threads
     maxdepth: u32,           // The maximum depth seen in this evaluation
     curdepth: u32,           // The current depth of evaluation
-    cachehits: u32,          // Number of times a cached result has been reused
+    cachehits: u64,          // Number of times a cached result has been reused
+    carriedhits: u64,        // Number of those which came from the search for an earlier move
+    generation: u16,         // The generation of the cache this evaluation's entries belong to
     moves_evaled: u64,       // Number of game states evaluated in this evaluation
     depth_limit: u32,        // The maximum depth to look in this evaluation
+    budget: Option<&'a Budget>, // The budget this evaluation counts its nodes against, if it has one
+    abortable: bool,         // Whether this evaluation gives up when the budget runs out
+    aborted: bool,           // Set once this evaluation has given up, after which its values mean nothing
 }
 
-// Takes a board and returns the most effective move to make on it
-pub fn find_best_move(board: u64) -> u8 {
-    let mut best: f32 = 0.0;
-    let mut bestmove: u8 = 0;
-
-    // For each possible move, evaluate the move with expectimax search and track the best result.
-    // Concurrent
-    let mut threads = vec!();
-    for mv in 0..4 {
-        let handle = thread::spawn(move || {
-            (score_toplevel_move(board, mv), mv)
-        });
-        
-        threads.push(handle);
+impl<'a, P: Position, E: Heuristic + ?Sized> EvalState<'a, P, E> {
+    // Counts the nodes searched since the last check against the budget, and gives up if it has run out
+    fn check_budget(&mut self) {
+        if let Some(budget) = self.budget {
+            if budget.spend(BUDGET_CHECK_INTERVAL) && self.abortable {
+                self.aborted = true;
+            }
+        }
+    }
+}
+
+// Takes a board of any size and returns the most effective move to make on it, or None if no move changes the
+// board. 4x4 boards are searched as bitboards, which is much faster.
+pub fn find_best_grid_move<const W: usize, const H: usize, E>(grid: Grid<W, H>, config: &SearchConfig, heuristic: &E) -> Option<Direction>
+    where E: Heuristic + ?Sized
+{
+    search_grid(grid, config, heuristic).best
+}
+
+// As find_best_grid_move, returning everything the search found and did
+pub fn search_grid<const W: usize, const H: usize, E>(grid: Grid<W, H>, config: &SearchConfig, heuristic: &E) -> SearchResult
+    where E: Heuristic + ?Sized
+{
+    match search_bitboard(grid) {
+        Some(board) => search(board, config, heuristic),
+        None        => search(grid, config, heuristic),
     }
+}
+
+// As search_grid, reading and filling the given cache, which should be kept for the rest of the game
+pub fn search_grid_cached<const W: usize, const H: usize, E>(grid: Grid<W, H>, config: &SearchConfig, heuristic: &E,
+                                                            cache: &mut GridSearchCache<W, H>) -> SearchResult
+    where E: Heuristic + ?Sized
+{
+    match search_bitboard(grid) {
+        Some(board) => search_cached(board, config, heuristic, cache.board.get_or_insert_with(|| SearchCache::new(config))),
+        None        => search_cached(grid, config, heuristic, cache.grid.get_or_insert_with(|| SearchCache::new(config))),
+    }
+}
+
+// Returns the bitboard to search in place of the grid, if it is 4x4 and the bitboard tables can play it exactly
+fn search_bitboard<const W: usize, const H: usize>(grid: Grid<W, H>) -> Option<Board> {
+    grid.to_bitboard().filter(|_| grid.max_rank() < MAX_BITBOARD_RANK)
+}
 
-    for thread in threads {
+// Takes a board and returns the most effective move to make on it according to the given heuristic, or None if
+// no move changes the board
+pub fn find_best_move<P: Position, E: Heuristic + ?Sized>(board: P, config: &SearchConfig, heuristic: &E) -> Option<Direction> {
+    search(board, config, heuristic).best
+}
 
-        let (res, mv) = thread.join().unwrap();
+// As find_best_move, returning everything the search found and did
+pub fn search<P: Position, E: Heuristic + ?Sized>(board: P, config: &SearchConfig, heuristic: &E) -> SearchResult {
+    search_cached(board, config, heuristic, &mut SearchCache::one_shot(config))
+}
 
+// As search, reading and filling the given cache, which should be kept for the rest of the game. The cache is
+// only used with config.shared_table; otherwise each top level move has a cache of its own, kept for just the
+// one search.
+pub fn search_cached<P: Position, E: Heuristic + ?Sized>(board: P, config: &SearchConfig, heuristic: &E, cache: &mut SearchCache<P>)
+    -> SearchResult
+{
+    let start = Instant::now();
+    let legal = board.legal_moves();
+    let moves: Vec<Direction> = Direction::ALL.iter().cloned().filter(|mv| legal & mv.mask() != 0).collect();
+
+    let mut result = SearchResult {best: None, values: [0.0; 4], depth: 0, max_depth: 0, stats: SearchStats::default(), elapsed: Duration::ZERO};
+    if config.shared_table {
+        result.stats.carried_entries = cache.table.next_generation(config.cache_generations);
+    }
+    let shared = if config.shared_table {Some(&*cache)} else {None};
+
+    // Without a budget there is one search to the depth limit. Otherwise this is an anytime search, which deepens
+    // one level at a time, keeping the values of the deepest search which finished. The first search always
+    // finishes, so there is a move however small the budget.
+    let budget = if config.time_budget.is_none() && config.node_budget.is_none() {None} else {Some(Budget::new(config))};
+    let depths = match budget {
+        None    => config.depth_limit(board)..=config.depth_limit(board),
+        Some(_) => 1..=MAX_ITERATIVE_DEPTH,
+    };
+    for depth in depths {
+        let searched = search_moves(board, &moves, depth, config, heuristic, shared, budget.as_ref(), depth > 1);
+        result.stats.add(searched.stats);
+        let values = match searched.values {
+            Some(values) => values,
+            None         => break,
+        };
+        result.best = best_move(&moves, &values);
+        result.values = [0.0; 4];
+        for (&mv, &value) in moves.iter().zip(values.iter()) {
+            result.values[mv.index()] = value;
+        }
+        result.depth = depth;
+        result.max_depth = searched.maxdepth;
+
+        // If nothing reached the depth limit, the probability threshold cut off every line first and searching
+        // deeper would find the same
+        if searched.maxdepth < depth || budget.as_ref().is_some_and(Budget::is_exhausted) {
+            break;
+        }
+    }
+    result.elapsed = start.elapsed();
+    result
+}
+
+// Returns the move with the highest value. Ties go to the first move in Direction::ALL, however the moves were
+// shared out between threads.
+fn best_move(moves: &[Direction], results: &[f32]) -> Option<Direction> {
+    let mut best: f32 = 0.0;
+    let mut bestmove: Option<Direction> = None;
+    for (&res, &mv) in results.iter().zip(moves.iter()) {
         if res > best {
             best = res;
-            bestmove = mv;
+            bestmove = Some(mv);
         }
     }
     bestmove
 }
 
+// The result of evaluating some moves
+struct Searched {
+    values: Option<Vec<f32>>, // The value of each move, or None if the evaluation gave up
+    maxdepth: u32,            // The deepest any line went
+    stats: SearchStats,
+}
+
+// Evaluates each of the given moves with expectimax search to the given depth. The evaluation gives up if it is
+// abortable and the budget runs out before it finishes.
+#[allow(clippy::too_many_arguments)]
+fn search_moves<P: Position, E: Heuristic + ?Sized>(board: P, moves: &[Direction], depth_limit: u32, config: &SearchConfig, heuristic: &E,
+                                                   shared: Option<&SearchCache<P>>, budget: Option<&Budget>, abortable: bool) -> Searched {
+    // The moves are shared out between the threads, each searching every workers-th move. With a shared cache
+    // they all read and fill it, so a position reachable from several moves is only searched once.
+    let workers = config.search_threads().min(moves.len().max(1));
+    let mut results = vec!(None; moves.len());
+    let mut maxdepth = 0;
+    let mut stats = SearchStats::default();
+
+    if workers == 1 {
+        for (res, &mv) in results.iter_mut().zip(moves.iter()) {
+            let evaluation = score_toplevel_move(board, mv, config, heuristic, depth_limit, shared, budget, abortable);
+            *res = evaluation.value;
+            maxdepth = max(maxdepth, evaluation.maxdepth);
+            stats.add(evaluation.stats);
+        }
+    } else {
+        thread::scope(|scope| {
+            let mut threads = vec!();
+            for worker in 0..workers {
+                let handle = scope.spawn(move || {
+                    let cpu_start = cpu_time(CpuClock::Thread);
+                    let evaluations = moves.iter().enumerate().skip(worker).step_by(workers)
+                        .map(|(i, &mv)| (i, score_toplevel_move(board, mv, config, heuristic, depth_limit, shared, budget, abortable)))
+                        .collect::<Vec<_>>();
+                    let cpu_used = cpu_start.and_then(|start| cpu_time(CpuClock::Thread).map(|end| end - start));
+                    (evaluations, cpu_used.unwrap_or_default())
+                });
+
+                threads.push(handle);
+            }
+
+            for thread in threads {
+                let (evaluations, cpu_used) = thread.join().unwrap();
+                stats.worker_cpu_time += cpu_used;
+                for (i, evaluation) in evaluations {
+                    results[i] = evaluation.value;
+                    maxdepth = max(maxdepth, evaluation.maxdepth);
+                    stats.add(evaluation.stats);
+                }
+            }
+        });
+    }
+
+    Searched { values: results.into_iter().collect(), maxdepth, stats }
+}
+
 // Returns the value of a player node in the game tree.
 // Plays the part of the Maximiser node in the Expectimax search.
-fn score_move_node(mut state: &mut EvalState, board: u64, cprob: f32) -> f32 {
+fn score_move_node<P: Position, E: Heuristic + ?Sized>(state: &mut EvalState<P, E>, board: P, cprob: f32) -> f32 {
     let mut best: f32 = 0.0;
     state.curdepth+= 1;
     // Look at each possible move and track the highest value
-    for mv in 0..4 {
-        let newboard: u64 = execute_move(mv, board);
+    for &mv in &Direction::ALL {
+        // Once the evaluation has given up, stop counting moves
+        if state.aborted {
+            break;
+        }
+        let newboard = board.execute_move(mv);
         state.moves_evaled+= 1;
+        if state.moves_evaled.is_multiple_of(BUDGET_CHECK_INTERVAL) {
+            state.check_budget();
+        }
 
         if board != newboard {
-            unsafe {
-                best = best.max(score_tilechoose_node(&mut state, newboard, cprob));
-            }
+            best = best.max(score_spawns(state, newboard, cprob));
         }
     }
     state.curdepth -= 1;
@@ -76,78 +682,133 @@ fn score_move_node(mut state: &mut EvalState, board: u64, cprob: f32) -> f32 {
     best
 }
 
-// Returns the value of a computer node in the game tree.
+// Returns the value of the computer's turn after a move, which places as many tiles as the rules spawn per turn.
+fn score_spawns<P: Position, E: Heuristic + ?Sized>(state: &mut EvalState<P, E>, board: P, cprob: f32) -> f32 {
+    if state.config.rules.per_turn == 0 {
+        // No tiles are placed, so there is no chance node to stop the search at the depth limit; it stops here
+        if state.aborted {
+            return 0.0;
+        }
+        if state.curdepth >= state.depth_limit {
+            state.maxdepth = max(state.curdepth, state.maxdepth);
+            return board.heuristic(state.heuristic);
+        }
+        return score_move_node(state, board, cprob);
+    }
+    score_tilechoose_node(state, board, cprob, state.config.rules.per_turn)
+}
+
+// Returns the value of a computer node in the game tree, which still has spawns_left tiles to place this turn.
 // Plays the part of the Expected Value node in the Expectimax search.
-unsafe fn score_tilechoose_node(mut state: &mut EvalState, board:u64, mut cprob:f32) -> f32 {
+fn score_tilechoose_node<P: Position, E: Heuristic + ?Sized>(state: &mut EvalState<P, E>, board: P, mut cprob:f32, spawns_left: usize) -> f32 {
+    // Once the evaluation has given up, unwind as quickly as possible
+    if state.aborted {
+        return 0.0;
+    }
     // Base case: simply return the heuristic if the current state is less likely than the threshold
     // or deeper than the depth limit
-    if cprob < CPROB_THRESH_BASE || state.curdepth >= state.depth_limit {
+    if cprob < state.config.prob_threshold || state.curdepth >= state.depth_limit {
         state.maxdepth = max(state.curdepth, state.maxdepth);
-        return score_heur_board(board);
+        return board.heuristic(state.heuristic);
     }
-    // If the current depth is less than the cache depth limit, look in the cache in case we already know 
-    // the value of this board.
-    if state.curdepth < CACHE_DEPTH_LIMIT {
-        
-        let entry = state.trans_table.get(&board);  
+    // If the current depth is less than the cache depth limit, look in the cache in case we already know
+    // the value of this board. Only the first spawn of a turn is cached, as the board alone does not say how
+    // many spawns are left.
+    let cacheable = state.curdepth < state.config.cache_depth_limit && spawns_left == state.config.rules.per_turn;
+    let budget = NodeBudget::new(state.depth_limit - state.curdepth, cprob);
+    // Rotations and reflections of a board have the same value, up to rounding, so can share an entry
+    let key = if cacheable && state.config.canonical_keys {board.canonical()} else {board};
+    if cacheable {
+        cprob = budget.probability();
         // If we have cached this entry, return the cached value
-        if let Some(entry) = entry {
-            if entry.depth <= state.curdepth as u8 {
-                state.cachehits+= 1;
-                return entry.heuristic;
+        if let Some(entry) = state.trans_table.get(&key, budget, state.config.exact_budgets) {
+            state.cachehits+= 1;
+            if entry.generation != state.generation {
+                state.carriedhits+= 1;
             }
+            return entry.heuristic;
         }
     }
 
     // We have not cached this board, calculate the value
     // Scale the probability of the children of this node by the number of possible choices.
-    let num_open = count_empty(board);
+    let num_open = board.count_empty();
     cprob /= num_open as f32;
 
     let mut res: f32 = 0.0;
-    let mut tmp = board;
-    let mut tile_2: u64 = 1;
-    
-    // For each empty tile on the board, add a two and a four to it and calculate the value of it by 
-    // simulating another human (move_node) move. 
-    while tile_2 != 0 {
-        if (tmp & 0xF) == 0 {
-            res += score_move_node(&mut state, board |  tile_2      , cprob * 0.9) * 0.9;
-            res += score_move_node(&mut state, board | (tile_2 << 1), cprob * 0.1) * 0.1;
+
+    // For each empty tile on the board, add each tile the rules can spawn to it and calculate the value of it by
+    // placing the rest of this turn's tiles, then simulating another human (move_node) move.
+    for cell in 0..board.cells() {
+        if board.is_empty_cell(cell) {
+            for &(rank, probability) in &state.config.rules.tiles {
+                let newboard = board.with_tile(cell, rank);
+                let value = if spawns_left > 1 && num_open > 1 {
+                    score_tilechoose_node(state, newboard, cprob * probability, spawns_left - 1)
+                } else {
+                    score_move_node(state, newboard, cprob * probability)
+                };
+                res += value * probability;
+            }
         }
-        tmp >>= 4;
-        tile_2 <<= 4;
     }
 
     res /= num_open as f32;
 
-    // If we aren't too deep, cache this result for next time.
-    if state.curdepth < CACHE_DEPTH_LIMIT {
-        let entry = TransTableEntry {depth: state.curdepth as u8, heuristic: res};
-        state.trans_table.insert(board, entry);
+    // If we aren't too deep, cache this result for next time. A value from an evaluation which gave up part way
+    // is wrong, so is never cached.
+    if cacheable && !state.aborted {
+        let entry = TransTableEntry {budget, generation: state.generation, heuristic: res};
+        state.trans_table.insert(key, entry, state.config.exact_budgets);
     }
 
     res
 }
 
 // Takes a move and a board and evaluates the value of that move. Begins the expectimax search on this state
-fn _score_toplevel_move(mut state: &mut EvalState, board: u64, mv: u8) -> f32 {
-    let newboard = execute_move(mv, board);
+fn _score_toplevel_move<P: Position, E: Heuristic + ?Sized>(state: &mut EvalState<P, E>, board: P, mv: Direction) -> f32 {
+    let newboard = board.execute_move(mv);
 
     if board == newboard {
         return 0.0;
     }
 
-    unsafe {
-        score_tilechoose_node(&mut state, newboard, 1.0) + 0.000001
-    }
+    score_spawns(state, newboard, 1.0) + 0.000001
 }
 
-// Takes a board and a move and sets up the infrastructure to perform the expectimax search on it.
-fn score_toplevel_move(board: u64, mv: u8) -> f32 {
-    let mut state = EvalState{maxdepth: 0, curdepth: 0, moves_evaled: 0, cachehits:0, depth_limit:0, trans_table: TransTable::new()};
-    state.depth_limit = max(3, (count_distinct_tiles(board) - 2));
-
-    _score_toplevel_move(&mut state, board, mv)
+// The result of evaluating one top level move
+struct Evaluation {
+    value: Option<f32>, // The value of the move, or None if the evaluation gave up
+    maxdepth: u32,      // The deepest any line went
+    stats: SearchStats,
 }
 
+// Takes a board and a move and sets up the infrastructure to perform the expectimax search on it, to the given
+// depth. Uses the shared cache if there is one, and otherwise a cache of its own.
+#[allow(clippy::too_many_arguments)]
+fn score_toplevel_move<P: Position, E: Heuristic + ?Sized>(board: P, mv: Direction, config: &SearchConfig, heuristic: &E, depth_limit: u32,
+                                                          shared: Option<&SearchCache<P>>, budget: Option<&Budget>, abortable: bool) -> Evaluation {
+    let own;
+    let (trans_table, generation) = match shared {
+        Some(cache) => (&cache.table, cache.table.generation),
+        None => {
+            own = TransTable::new(config.search_cache_mb, 1);
+            (&own, 0)
+        }
+    };
+    // An evaluation which may give up does not start once the budget has run out
+    let aborted = abortable && budget.is_some_and(Budget::is_exhausted);
+    let mut state = EvalState{config, heuristic, maxdepth: 0, curdepth: 0, moves_evaled: 0, cachehits: 0, carriedhits: 0,
+                              generation, depth_limit, trans_table, budget, abortable, aborted};
+
+    let value = _score_toplevel_move(&mut state, board, mv);
+    // Count the nodes searched since the last check, so that the next evaluation sees them
+    if let Some(budget) = budget {
+        budget.spend(state.moves_evaled % BUDGET_CHECK_INTERVAL);
+    }
+    Evaluation {
+        value: if state.aborted {None} else {Some(value)},
+        maxdepth: state.maxdepth,
+        stats: SearchStats {nodes: state.moves_evaled, cache_hits: state.cachehits, carried_hits: state.carriedhits, ..SearchStats::default()},
+    }
+}
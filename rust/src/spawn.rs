@@ -0,0 +1,41 @@
+use rand::Rng;
+
+// The rules deciding which tiles the game spawns and how many. Shared by the game, which draws from them,
+// and the search, which weighs its chance nodes by them.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpawnRules {
+    pub tiles: Vec<(u8, f32)>, // Each tile rank that can spawn and its probability. The probabilities sum to 1.
+    pub per_turn: usize,       // Number of tiles spawned after each move
+    pub initial: usize,        // Number of tiles on the board at the start of a game
+}
+
+impl SpawnRules {
+    // Returns the standard rules with the given chance of a spawned tile being a 4 rather than a 2
+    pub fn with_four_probability(four: f32) -> SpawnRules {
+        SpawnRules {tiles: vec!((1, 1.0 - four), (2, four)), ..SpawnRules::default()}
+    }
+
+    // Returns the rank of a randomly drawn tile, or None if the rules spawn no tiles at all
+    pub fn draw_tile<R: Rng>(&self, rng: &mut R) -> Option<u8> {
+        let mut roll: f32 = rng.gen();
+        for &(rank, probability) in &self.tiles {
+            if roll < probability {
+                return Some(rank);
+            }
+            roll -= probability;
+        }
+        // Only reachable through rounding in the probabilities
+        self.tiles.last().map(|tile| tile.0)
+    }
+}
+
+// The standard game: one tile per move, two to start with, and a 10% chance of a 4.
+impl Default for SpawnRules {
+    fn default() -> SpawnRules {
+        SpawnRules {
+            tiles: vec!((1, 0.9), (2, 0.1)),
+            per_turn: 1,
+            initial: 2,
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Statistics for summarising and comparing benchmark results. Everything works on f64 samples, and
+// intervals are given at a confidence level between 0 and 1, e.g. 0.95.
+use std::f64::consts::PI;
+
+// Returns the mean of the sample, or NaN if it is empty
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Returns the sample standard deviation, which is 0 for fewer than two values
+pub fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let squares: f64 = values.iter().map(|x| (x - mean) * (x - mean)).sum();
+    (squares / (values.len() - 1) as f64).sqrt()
+}
+
+// Returns the q quantile of the sample, interpolating linearly between the nearest values, or NaN if it is
+// empty. The median is quantile(values, 0.5).
+pub fn quantile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+// Returns the median of the sample
+pub fn median(values: &[f64]) -> f64 {
+    quantile(values, 0.5)
+}
+
+// Returns the confidence interval for the mean of the population the sample was drawn from, using Student's
+// t distribution. The interval is a single point for fewer than two values.
+pub fn mean_interval(values: &[f64], confidence: f64) -> (f64, f64) {
+    let mean = mean(values);
+    if values.len() < 2 {
+        return (mean, mean);
+    }
+    let n = values.len() as f64;
+    let margin = t_quantile(0.5 + confidence / 2.0, n - 1.0) * std_dev(values) / n.sqrt();
+    (mean - margin, mean + margin)
+}
+
+// Returns the Wilson score interval for a proportion, given the number of successes in a number of trials.
+// Unlike the normal approximation it stays within [0, 1] and behaves at 0% and 100%.
+pub fn wilson_interval(successes: usize, trials: usize, confidence: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let z = normal_quantile(0.5 + confidence / 2.0);
+    let z2 = z * z;
+
+    let centre = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((centre - margin).max(0.0), (centre + margin).min(1.0))
+}
+
+// A summary of a sample
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub q25: f64,
+    pub median: f64,
+    pub q75: f64,
+    pub max: f64,
+    pub mean_low: f64,  // The confidence interval for the mean
+    pub mean_high: f64,
+}
+
+impl Summary {
+    // Summarises the sample, with the interval for the mean at the given confidence
+    pub fn of(values: &[f64], confidence: f64) -> Summary {
+        let (mean_low, mean_high) = mean_interval(values, confidence);
+        Summary {
+            count: values.len(),
+            mean: mean(values),
+            std_dev: std_dev(values),
+            min: quantile(values, 0.0),
+            q25: quantile(values, 0.25),
+            median: median(values),
+            q75: quantile(values, 0.75),
+            max: quantile(values, 1.0),
+            mean_low,
+            mean_high,
+        }
+    }
+}
+
+// The result of testing whether two samples have the same mean
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Comparison {
+    pub difference: f64,      // The mean of b less the mean of a
+    pub low: f64,             // The confidence interval for the difference
+    pub high: f64,
+    pub t: f64,               // The test statistic
+    pub p_value: f64,         // The two sided probability of a difference at least this big if the means are equal
+    pub significant: bool,    // Whether p_value is below the significance level, 1 - confidence
+}
+
+// Compares two samples taken in pairs, such as games played with the same seeds under two configurations,
+// with a paired t test. The samples must be the same length.
+pub fn paired_t_test(a: &[f64], b: &[f64], confidence: f64) -> Comparison {
+    assert_eq!(a.len(), b.len(), "paired samples must be the same length");
+    let differences: Vec<f64> = a.iter().zip(b.iter()).map(|(a, b)| b - a).collect();
+    let difference = mean(&differences);
+    let (low, high) = mean_interval(&differences, confidence);
+
+    let n = differences.len() as f64;
+    let error = std_dev(&differences) / n.sqrt();
+    let (t, p_value) = if differences.len() < 2 {
+        (0.0, 1.0)
+    } else if error == 0.0 {
+        // Every pair differs by the same amount, so the difference is certain unless it is nothing
+        if difference == 0.0 {(0.0, 1.0)} else {(difference.signum() * f64::INFINITY, 0.0)}
+    } else {
+        let t = difference / error;
+        (t, 2.0 * (1.0 - t_cdf(t.abs(), n - 1.0)))
+    };
+
+    Comparison { difference, low, high, t, p_value, significant: p_value < 1.0 - confidence }
+}
+
+// Returns the quantile function of the standard normal distribution at p, by Acklam's rational approximation
+// (relative error below 1.2e-9)
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e+01,  2.209460984245205e+02, -2.759285104469687e+02,
+                          1.38357751867269e+02, -3.066479806614716e+01,  2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01,  1.615858368580409e+02, -1.556989798598866e+02,
+                          6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+                         -2.549732539343734e+00,  4.374664141464968e+00,  2.938163982698783e+00];
+    const D: [f64; 4] = [ 7.784695709041462e-03,  3.224671290700398e-01,  2.445134137142996e+00,
+                          3.754408661907416e+00];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    if p < LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0]*q + C[1])*q + C[2])*q + C[3])*q + C[4])*q + C[5]) / ((((D[0]*q + D[1])*q + D[2])*q + D[3])*q + 1.0)
+    } else if p <= 1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0]*r + A[1])*r + A[2])*r + A[3])*r + A[4])*r + A[5])*q / (((((B[0]*r + B[1])*r + B[2])*r + B[3])*r + B[4])*r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+// Returns the quantile function of Student's t distribution with the given degrees of freedom at p. Exact for
+// one and two degrees of freedom, and otherwise found from the distribution function by bisection.
+pub fn t_quantile(p: f64, df: f64) -> f64 {
+    if df == 1.0 {
+        return (PI * (p - 0.5)).tan();
+    }
+    if df == 2.0 {
+        return (2.0 * p - 1.0) / (2.0 * p * (1.0 - p)).sqrt();
+    }
+    if p < 0.5 {
+        return -t_quantile(1.0 - p, df);
+    }
+
+    // The t quantile is always further out than the normal one, which gives the bisection a starting bracket
+    let mut low = normal_quantile(p).max(0.0);
+    let mut high = low.max(1.0);
+    while t_cdf(high, df) < p {
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if t_cdf(mid, df) < p {low = mid} else {high = mid}
+    }
+    (low + high) / 2.0
+}
+
+// Returns the distribution function of Student's t distribution with the given degrees of freedom at t
+pub fn t_cdf(t: f64, df: f64) -> f64 {
+    let tail = 0.5 * incomplete_beta(df / (df + t * t), df / 2.0, 0.5);
+    if t > 0.0 {1.0 - tail} else {tail}
+}
+
+// Returns the regularised incomplete beta function I_x(a, b), evaluated by continued fraction
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only on one side of the mean, so use the symmetry otherwise
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+// Evaluates the continued fraction for the incomplete beta function by the modified Lentz method
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {d = TINY;}
+    d = 1.0 / d;
+    let mut result = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        // Even step
+        let numerator = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY {d = TINY;}
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {c = TINY;}
+        d = 1.0 / d;
+        result *= d * c;
+
+        // Odd step
+        let numerator = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY {d = TINY;}
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {c = TINY;}
+        d = 1.0 / d;
+        let step = d * c;
+        result *= step;
+
+        if (step - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    result
+}
+
+// Returns the natural log of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+                                    771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905,
+                                    -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7];
+    if x < 0.5 {
+        // Reflection formula
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, &coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
@@ -0,0 +1,196 @@
+// Parameter sweeps for the harness. Each parameter is a SearchConfig setting or a weight of the row heuristic,
+// given a list of values, a stepped range or a range to sample from. A sweep either plays every combination of
+// the values (a grid) or draws a number of random points from them.
+use rand::Rng;
+
+use twenty48::board::seeded_rng;
+use twenty48::search::SearchConfig;
+use twenty48::spawn::SpawnRules;
+use twenty48::RowWeights;
+
+// The parameters a sweep can vary, with whether each takes whole numbers only
+pub const PARAMETERS: [(&str, bool); 18] = [
+    ("threshold",           false),
+    ("cache_depth_limit",   true),
+    ("min_depth",           true),
+    ("depth_offset",        true),
+    ("four_probability",    false),
+    ("shared_table",        true),
+    ("cache_generations",   true),
+    ("cache_mb",            true),
+    ("search_cache_mb",     true),
+    ("canonical_keys",      true),
+    ("exact_budgets",       true),
+    ("lost_penalty",        false),
+    ("monotonicity_power",  false),
+    ("monotonicity_weight", false),
+    ("sum_power",           false),
+    ("sum_weight",          false),
+    ("merges_weight",       false),
+    ("empty_weight",        false),
+];
+
+// The values of a stepped range are rounded to billionths, and the last may be a billionth of a step past its end
+const RANGE_SCALE: f64 = 1e9;
+
+// The values a parameter can take in a sweep
+#[derive(Clone, PartialEq, Debug)]
+pub enum Values {
+    List(Vec<f64>),  // Exactly these values
+    Range(f64, f64), // Anywhere between these, for random sampling only
+}
+
+// A parameter and the values it takes
+#[derive(Clone, PartialEq, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub values: Values,
+    integer: bool,
+}
+
+impl Parameter {
+    // Reads a parameter from `name=a,b,c` (a list), `name=low:high:step` (a stepped range) or `name=low:high`
+    // (a range to sample from)
+    pub fn parse(spec: &str) -> Result<Parameter, String> {
+        let (name, values) = match spec.find('=') {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None    => return Err(format!("parameter '{}' needs values, as name=a,b,c or name=low:high[:step]", spec)),
+        };
+        let integer = match PARAMETERS.iter().find(|parameter| parameter.0 == name) {
+            Some(parameter) => parameter.1,
+            None => {
+                let names: Vec<&str> = PARAMETERS.iter().map(|parameter| parameter.0).collect();
+                return Err(format!("unknown parameter '{}', expected one of {}", name, names.join(", ")));
+            }
+        };
+
+        let number = |s: &str| s.trim().parse::<f64>().map_err(|_| format!("invalid value '{}' for {}", s, name));
+        let values = if values.contains(':') {
+            let bounds = values.split(':').map(number).collect::<Result<Vec<f64>, String>>()?;
+            match bounds.len() {
+                2 => Values::Range(bounds[0], bounds[1]),
+                3 if bounds[2] > 0.0 => {
+                    // Allow a little past the end so rounding cannot drop the last value, and round the steps
+                    // so that 0:1:0.1 gives 0.3 rather than 0.30000000000000004
+                    let count = ((bounds[1] - bounds[0]) / bounds[2] + 1.0 / RANGE_SCALE).floor().max(0.0) as usize + 1;
+                    let step = |i: usize| ((bounds[0] + i as f64 * bounds[2]) * RANGE_SCALE).round() / RANGE_SCALE;
+                    Values::List((0..count).map(step).collect())
+                }
+                _ => return Err(format!("invalid range '{}' for {}, expected low:high or low:high:step", values, name)),
+            }
+        } else {
+            Values::List(values.split(',').map(number).collect::<Result<_, _>>()?)
+        };
+
+        Ok(Parameter {name: name.to_string(), values, integer})
+    }
+
+    // Returns the given value as this parameter takes it
+    fn round(&self, value: f64) -> f64 {
+        if self.integer {value.round().max(0.0)} else {value}
+    }
+}
+
+// Returns every combination of the parameters' values, the first parameter varying slowest
+pub fn grid(parameters: &[Parameter]) -> Result<Vec<Vec<f64>>, String> {
+    let mut points = vec!(vec!());
+    for parameter in parameters {
+        let values = match parameter.values {
+            Values::List(ref values) => values,
+            Values::Range(..) => return Err(format!("{} is given a range to sample from, so needs --samples", parameter.name)),
+        };
+        points = points.iter()
+            .flat_map(|point: &Vec<f64>| values.iter().map(move |&value| {
+                let mut point = point.clone();
+                point.push(parameter.round(value));
+                point
+            }))
+            .collect();
+    }
+    Ok(points)
+}
+
+// Returns the given number of points drawn at random from the parameters' values, decided by the seed
+pub fn sample(parameters: &[Parameter], samples: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = seeded_rng(seed);
+    (0..samples).map(|_| {
+        parameters.iter().map(|parameter| {
+            let value = match parameter.values {
+                Values::List(ref values) => values[rng.gen_range(0, values.len())],
+                Values::Range(low, high) => low + (high - low) * rng.gen::<f64>(),
+            };
+            parameter.round(value)
+        }).collect()
+    }).collect()
+}
+
+// Sets the named parameter to the given value in the search settings or the heuristic weights
+pub fn apply(name: &str, value: f64, config: &mut SearchConfig, weights: &mut RowWeights) -> Result<(), String> {
+    match name {
+        "threshold"           => config.prob_threshold = value as f32,
+        "cache_depth_limit"   => config.cache_depth_limit = value as u32,
+        "min_depth"           => config.min_depth = value as u32,
+        "depth_offset"        => config.depth_offset = value as u32,
+        "four_probability"    => {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("four_probability must be between 0 and 1, not {}", value));
+            }
+            config.rules = SpawnRules {per_turn: config.rules.per_turn, initial: config.rules.initial,
+                                       ..SpawnRules::with_four_probability(value as f32)};
+        }
+        "shared_table"        => config.shared_table = value != 0.0,
+        "cache_generations"   => config.cache_generations = value as u32,
+        "cache_mb"            => config.cache_mb = value as usize,
+        "search_cache_mb"     => config.search_cache_mb = value as usize,
+        "canonical_keys"      => config.canonical_keys = value != 0.0,
+        "exact_budgets"       => config.exact_budgets = value != 0.0,
+        "lost_penalty"        => weights.lost_penalty = value as f32,
+        "monotonicity_power"  => weights.monotonicity_power = value as f32,
+        "monotonicity_weight" => weights.monotonicity_weight = value as f32,
+        "sum_power"           => weights.sum_power = value as f32,
+        "sum_weight"          => weights.sum_weight = value as f32,
+        "merges_weight"       => weights.merges_weight = value as f32,
+        "empty_weight"        => weights.empty_weight = value as f32,
+        _                     => return Err(format!("unknown parameter '{}'", name)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(spec: &str) -> Vec<f64> {
+        match Parameter::parse(spec).unwrap().values {
+            Values::List(values) => values,
+            Values::Range(..)    => panic!("{} is not a list", spec),
+        }
+    }
+
+    #[test]
+    fn stepped_ranges_end_at_or_before_their_high_value() {
+        assert_eq!(values("threshold=0:1:0.1"), vec!(0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0));
+        assert_eq!(values("threshold=0.001:0.003:0.001"), vec!(0.001, 0.002, 0.003));
+        assert_eq!(values("sum_weight=0:1:0.35"), vec!(0.0, 0.35, 0.7));
+        assert_eq!(values("sum_weight=0:1:0.4"), vec!(0.0, 0.4, 0.8));
+        assert_eq!(values("min_depth=2:2:1"), vec!(2.0));
+        assert_eq!(values("min_depth=3:2:1"), vec!(3.0));
+        assert_eq!(values("sum_weight=1,2.5"), vec!(1.0, 2.5));
+        assert_eq!(Parameter::parse("sum_weight=1:2").unwrap().values, Values::Range(1.0, 2.0));
+        for spec in ["sum_weight", "colour=1,2", "sum_weight=1:2:0", "sum_weight=1:2:3:4", "sum_weight=a"] {
+            assert!(Parameter::parse(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn whole_number_parameters_are_rounded() {
+        let parameters = [Parameter::parse("min_depth=2.4,2.6").unwrap(), Parameter::parse("sum_weight=0.5").unwrap()];
+        assert_eq!(grid(&parameters).unwrap(), vec!(vec!(2.0, 0.5), vec!(3.0, 0.5)));
+
+        let parameters = [Parameter::parse("cache_mb=1:8").unwrap()];
+        for point in sample(&parameters, 20, 1) {
+            assert!(point[0] == point[0].round() && (1.0..=8.0).contains(&point[0]));
+        }
+        assert!(grid(&parameters).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+// Timing for the harness: a histogram of how long the agent takes over each move.
+use std::time::Duration;
+
+// Each power of two is split into this many buckets, so a bucket is at most 1/16th of its values wide
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = 4;
+
+// A histogram of durations with a bucket for every nanosecond up to 16ns and 16 buckets for each power of two
+// beyond, so any quantile it reports is within 1/16th (about 6%) of the true one. Histograms of separate games
+// can be merged.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram {buckets: vec!(0; bucket_of(u64::MAX) + 1), count: 0, total: Duration::ZERO, max: Duration::ZERO}
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_of(nanos)] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    // Adds everything recorded in the other histogram to this one
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket, &count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    // Returns the mean of the durations recorded
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {Duration::ZERO} else {self.total / self.count as u32}
+    }
+
+    // Returns the longest duration recorded
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    // Returns the duration which the given fraction of those recorded are no longer than, rounded up to the top
+    // of its bucket
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_top(bucket)).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+// Returns the bucket holding the given number of nanoseconds
+fn bucket_of(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return nanos as usize;
+    }
+    let exponent = 63 - nanos.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub = (nanos >> shift) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS + sub) as usize
+}
+
+// Returns the largest number of nanoseconds in the given bucket
+fn bucket_top(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    let sub = bucket % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub) << shift).saturating_add((1u64 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_every_duration_once() {
+        // Every nanosecond up to 16 has a bucket of its own
+        for nanos in 0..SUB_BUCKETS {
+            assert_eq!(bucket_of(nanos), nanos as usize);
+            assert_eq!(bucket_top(nanos as usize), nanos);
+        }
+        // Beyond, each bucket starts just past the last one's top and is at most a 16th of its values wide
+        let mut bottom = SUB_BUCKETS;
+        for bucket in SUB_BUCKETS as usize..bucket_of(u64::MAX) {
+            let top = bucket_top(bucket);
+            assert_eq!(bucket_of(bottom), bucket);
+            assert_eq!(bucket_of(top), bucket);
+            assert!(top - bottom < bottom / SUB_BUCKETS, "bucket {} holds {} to {}", bucket, bottom, top);
+            bottom = top + 1;
+        }
+        assert_eq!(bucket_top(bucket_of(u64::MAX)), u64::MAX);
+    }
+
+    #[test]
+    fn quantiles_are_within_a_sixteenth_above_the_true_ones() {
+        let mut first = LatencyHistogram::default();
+        let mut second = LatencyHistogram::default();
+        assert_eq!(first.quantile(0.5), Duration::ZERO);
+        for micros in 1..=1000 {
+            let histogram = if micros % 2 == 0 {&mut first} else {&mut second};
+            histogram.record(Duration::from_micros(micros));
+        }
+        first.merge(&second);
+
+        for &(q, exact) in &[(0.01, 10), (0.5, 500), (0.95, 950), (0.99, 990)] {
+            let exact = Duration::from_micros(exact);
+            let quantile = first.quantile(q);
+            assert!(quantile >= exact && quantile <= exact + exact / 16, "quantile {} is {:?}", q, quantile);
+        }
+        assert_eq!(first.quantile(1.0), Duration::from_micros(1000));
+        assert_eq!(first.max(), Duration::from_micros(1000));
+        assert_eq!(first.mean(), Duration::from_nanos(500_500));
+    }
+}
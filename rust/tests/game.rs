@@ -0,0 +1,83 @@
+// Checks that a game keeps an exact score and that its seed and moves are enough to replay it.
+extern crate twenty48;
+
+use twenty48::{Direction, Game, GridGame, SpawnRules, WideBoard};
+use twenty48::board::seeded_rng;
+
+// Plays a game to the end by always taking the first legal move of left, up, right and down, which keeps the
+// big tiles in the top left corner for long enough to merge a few of them
+fn play_corner_game<const W: usize, const H: usize>(game: &mut GridGame<W, H>) -> u64 {
+    let mut points = 0;
+    while !game.is_over() {
+        let legal = game.legal_moves();
+        let mv = [Direction::Left, Direction::Up, Direction::Right, Direction::Down].iter().cloned()
+            .find(|mv| legal & mv.mask() != 0).unwrap();
+        points += game.play(mv).unwrap();
+    }
+    points
+}
+
+#[test]
+fn the_score_is_the_sum_of_the_merges() {
+    for seed in 1..6 {
+        let mut game = Game::new(seed);
+        let points = play_corner_game(&mut game);
+        assert_eq!(game.score(), points);
+
+        // A tile of rank r took r - 1 merges to build, each scoring the tile it made, so the board is worth
+        // (r - 1) * 2^r for each tile, less the 4 points not scored for every 4 that spawned ready made
+        let grid = game.grid();
+        let built: u64 = (0..WideBoard::CELLS).map(|cell| grid.rank(cell) as u64)
+            .filter(|&rank| rank > 0).map(|rank| (rank - 1) << rank).sum();
+        let spawned_fours = game.spawns().iter().filter(|spawn| spawn.rank == 2).count() as u64;
+        assert_eq!(game.score(), built - 4 * spawned_fours, "game with seed {}", seed);
+    }
+}
+
+#[test]
+fn the_seed_and_moves_replay_the_same_game() {
+    let mut game = Game::new(7);
+    play_corner_game(&mut game);
+
+    let mut replay = Game::new(game.seed());
+    for &mv in game.moves() {
+        replay.play(mv).unwrap();
+    }
+    assert_eq!(replay.grid(), game.grid());
+    assert_eq!(replay.score(), game.score());
+    assert_eq!(replay.spawns(), game.spawns());
+    assert!(replay.is_over());
+
+    // Another seed spawns other tiles
+    assert_ne!(Game::new(8).spawns(), &game.spawns()[..2]);
+}
+
+#[test]
+fn games_spawn_as_many_tiles_as_their_rules_say() {
+    let rules = SpawnRules {tiles: vec!((1, 0.75), (3, 0.25)), per_turn: 2, initial: 4};
+    let mut game = Game::with_rules(3, rules);
+    assert_eq!(game.spawns().len(), 4);
+    while !game.is_over() {
+        let legal = game.legal_moves();
+        let mv = Direction::ALL.iter().cloned().find(|mv| legal & mv.mask() != 0).unwrap();
+        let room = game.grid().make_move(mv).unwrap().0.count_empty() as usize;
+        let spawned = game.spawns().len();
+        game.play(mv).unwrap();
+        assert_eq!(game.spawns().len() - spawned, room.min(2));
+    }
+    assert!(game.spawns().iter().all(|spawn| spawn.rank == 1 || spawn.rank == 3));
+
+    // Rules which spawn nothing leave the board empty
+    let game = Game::with_rules(3, SpawnRules {tiles: vec!(), ..SpawnRules::default()});
+    assert!(game.spawns().is_empty() && game.is_over());
+}
+
+#[test]
+fn tiles_are_drawn_with_the_probabilities_of_the_rules() {
+    let rules = SpawnRules::with_four_probability(0.25);
+    let mut rng = seeded_rng(1);
+    let draws = 20_000;
+    let fours = (0..draws).filter(|_| rules.draw_tile(&mut rng) == Some(2)).count();
+    assert!((fours as f64 / draws as f64 - 0.25).abs() < 0.01, "{} fours in {} draws", fours, draws);
+    assert_eq!(SpawnRules {tiles: vec!(), ..rules}.draw_tile(&mut rng), None);
+}
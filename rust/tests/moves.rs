@@ -0,0 +1,197 @@
+// Checks that each Direction moves the tiles the way its name says.
+extern crate twenty48;
+
+use twenty48::{Board, Direction, Grid, MoveError, WideBoard};
+
+// Builds a board from rows of ranks, top row first and leftmost column first
+fn board_from_ranks(ranks: [[u64; 4]; 4]) -> Board {
+    let mut board = 0;
+    for (r, row) in ranks.iter().enumerate() {
+        for (c, &rank) in row.iter().enumerate() {
+            board |= rank << (16 * r + 4 * c);
+        }
+    }
+    Board(board)
+}
+
+// Builds a board of any size from rows of ranks, top row first and leftmost column first
+fn grid_from_ranks<const W: usize, const H: usize>(ranks: [[u8; W]; H]) -> Grid<W, H> {
+    let mut grid = Grid::empty();
+    for (r, row) in ranks.iter().enumerate() {
+        for (c, &rank) in row.iter().enumerate() {
+            grid = grid.with_rank(W * r + c, rank);
+        }
+    }
+    grid
+}
+
+#[test]
+fn up_moves_tiles_to_the_first_row() {
+    let board = board_from_ranks([[0, 0, 0, 0],
+                                  [0, 2, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [1, 0, 0, 3]]);
+    let expected = board_from_ranks([[1, 2, 0, 3],
+                                     [0, 0, 0, 0],
+                                     [0, 0, 0, 0],
+                                     [0, 0, 0, 0]]);
+    assert_eq!(board.make_move(Direction::Up), Ok(expected));
+}
+
+#[test]
+fn down_moves_tiles_to_the_last_row() {
+    let board = board_from_ranks([[1, 0, 0, 3],
+                                  [0, 0, 0, 0],
+                                  [0, 2, 0, 0],
+                                  [0, 0, 0, 0]]);
+    let expected = board_from_ranks([[0, 0, 0, 0],
+                                     [0, 0, 0, 0],
+                                     [0, 0, 0, 0],
+                                     [1, 2, 0, 3]]);
+    assert_eq!(board.make_move(Direction::Down), Ok(expected));
+}
+
+#[test]
+fn left_moves_tiles_to_the_first_column() {
+    let board = board_from_ranks([[0, 0, 0, 1],
+                                  [0, 2, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 3, 0]]);
+    let expected = board_from_ranks([[1, 0, 0, 0],
+                                     [2, 0, 0, 0],
+                                     [0, 0, 0, 0],
+                                     [3, 0, 0, 0]]);
+    assert_eq!(board.make_move(Direction::Left), Ok(expected));
+}
+
+#[test]
+fn right_moves_tiles_to_the_last_column() {
+    let board = board_from_ranks([[1, 0, 0, 0],
+                                  [0, 2, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 3, 0]]);
+    let expected = board_from_ranks([[0, 0, 0, 1],
+                                     [0, 0, 0, 2],
+                                     [0, 0, 0, 0],
+                                     [0, 0, 0, 3]]);
+    assert_eq!(board.make_move(Direction::Right), Ok(expected));
+}
+
+#[test]
+fn merges_happen_towards_the_move_direction() {
+    let board = board_from_ranks([[1, 1, 1, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 0, 0]]);
+    let left = board_from_ranks([[2, 1, 0, 0],
+                                 [0, 0, 0, 0],
+                                 [0, 0, 0, 0],
+                                 [0, 0, 0, 0]]);
+    let right = board_from_ranks([[0, 0, 1, 2],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 0, 0]]);
+    assert_eq!(board.make_move(Direction::Left), Ok(left));
+    assert_eq!(board.make_move(Direction::Right), Ok(right));
+}
+
+#[test]
+fn moves_which_change_nothing_are_reported() {
+    let board = board_from_ranks([[1, 2, 0, 0],
+                                  [3, 0, 0, 0],
+                                  [0, 0, 0, 0],
+                                  [0, 0, 0, 0]]);
+    assert_eq!(board.make_move(Direction::Up), Err(MoveError::NoChange));
+    assert_eq!(board.make_move(Direction::Left), Err(MoveError::NoChange));
+    assert!(board.make_move(Direction::Down).is_ok());
+    assert!(board.make_move(Direction::Right).is_ok());
+}
+
+#[test]
+fn direction_indices_round_trip() {
+    for (i, &dir) in Direction::ALL.iter().enumerate() {
+        assert_eq!(dir.index(), i);
+        assert_eq!(Direction::from_index(i), Some(dir));
+    }
+    assert_eq!(Direction::from_index(4), None);
+}
+
+#[test]
+fn legal_moves_agree_with_executing_each_move() {
+    let boards = [
+        board_from_ranks([[1, 2, 0, 0], [3, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]),
+        board_from_ranks([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]]),
+        board_from_ranks([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 2]]),
+        board_from_ranks([[5, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 9]]),
+    ];
+    for &board in &boards {
+        let mut expected = 0;
+        for &dir in &Direction::ALL {
+            if board.make_move(dir).is_ok() {
+                expected |= dir.mask();
+            }
+        }
+        assert_eq!(board.legal_moves(), expected);
+        assert_eq!(board.is_game_over(), expected == 0);
+    }
+    assert!(boards[1].is_game_over());
+}
+
+#[test]
+fn wide_boards_merge_past_32768() {
+    let board = WideBoard::from(board_from_ranks([[15, 15, 15, 0],
+                                                  [ 0,  0,  0, 0],
+                                                  [ 0,  0,  0, 0],
+                                                  [ 0,  0,  0, 0]]));
+    let (merged, points) = board.make_move(Direction::Left).unwrap();
+    assert_eq!(merged.rank(0), 16);
+    assert_eq!(merged.rank(1), 15);
+    assert_eq!(merged.rank(2), 0);
+    assert_eq!(points, 65536);
+    assert_eq!(merged.max_rank(), 16);
+
+    // The bitboard view shows two 32768s, but a 65536 and a 32768 cannot merge
+    assert!(merged.to_board().make_move(Direction::Left).is_ok());
+    assert_eq!(merged.make_move(Direction::Left), Err(MoveError::NoChange));
+    assert_eq!(merged.legal_moves() & Direction::Left.mask(), 0);
+}
+
+#[test]
+fn small_boards_move_like_the_standard_one() {
+    let board = grid_from_ranks([[1, 1, 2],
+                                 [0, 2, 0],
+                                 [3, 0, 3]]);
+    let left = grid_from_ranks([[2, 2, 0],
+                                [2, 0, 0],
+                                [4, 0, 0]]);
+    let up = grid_from_ranks([[1, 1, 2],
+                              [3, 2, 3],
+                              [0, 0, 0]]);
+    assert_eq!(board.make_move(Direction::Left), Ok((left, 4 + 16)));
+    assert_eq!(board.make_move(Direction::Up), Ok((up, 0)));
+    assert_eq!(up.make_move(Direction::Up), Err(MoveError::NoChange));
+}
+
+#[test]
+fn rectangular_boards_move_along_their_own_rows_and_columns() {
+    // Four wide and five high
+    let tall = grid_from_ranks([[1, 0, 0, 1],
+                                [1, 0, 0, 0],
+                                [1, 0, 0, 0],
+                                [1, 0, 0, 0],
+                                [1, 0, 0, 0]]);
+    let down = grid_from_ranks([[0, 0, 0, 0],
+                                [0, 0, 0, 0],
+                                [1, 0, 0, 0],
+                                [2, 0, 0, 0],
+                                [2, 0, 0, 1]]);
+    assert_eq!(tall.make_move(Direction::Down), Ok((down, 8)));
+
+    // Five wide and four high
+    let wide = grid_from_ranks([[1, 2, 3, 4, 5],
+                                [0, 0, 0, 0, 0],
+                                [0, 0, 0, 0, 0],
+                                [0, 0, 0, 0, 0]]);
+    assert_eq!(wide.legal_moves(), Direction::Down.mask());
+    assert!(!wide.is_game_over());
+}
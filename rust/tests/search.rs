@@ -0,0 +1,218 @@
+// Checks the expectimax search, and that its budgets bound it without changing what it plays.
+extern crate twenty48;
+
+use std::time::Duration;
+
+use twenty48::{init_tables, Board, Direction, Game, GridGame, RowHeuristic, SearchConfig, SpawnRules, WideBoard};
+use twenty48::search::{find_best_grid_move, find_best_move, search, search_cached, search_grid, SearchCache};
+
+// A board part way through a game, on which every move but left changes the board
+const BOARD: Board = Board(0x0001_0021_0032_0143);
+
+#[test]
+fn a_spent_budget_still_finds_a_legal_move() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    for config in [SearchConfig {node_budget: Some(1), ..SearchConfig::default()},
+                   SearchConfig {time_budget: Some(Duration::ZERO), ..SearchConfig::default()}] {
+        let mv = find_best_move(BOARD, &config, &heuristic).expect("a move is legal");
+        assert!(BOARD.legal_moves() & mv.mask() != 0);
+    }
+}
+
+#[test]
+fn a_node_budget_plays_the_same_move_every_time() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let config = SearchConfig {node_budget: Some(50_000), threads: 4, ..SearchConfig::default()};
+    let first = search(BOARD, &config, &heuristic);
+    for _ in 0..3 {
+        let again = search(BOARD, &config, &heuristic);
+        assert_eq!((again.best, again.values, again.depth), (first.best, first.values, first.depth));
+        assert_eq!(again.stats.nodes, first.stats.nodes);
+    }
+}
+
+#[test]
+fn a_node_budget_bounds_the_nodes_searched() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    // The budget is counted a check interval of 1024 nodes at a time, so it can be overrun by less than that
+    for budget in [100, 5_000, 50_000] {
+        let config = SearchConfig {prob_threshold: 0.0001, node_budget: Some(budget), ..SearchConfig::default()};
+        let result = search(BOARD, &config, &heuristic);
+        assert!(result.stats.nodes <= budget + 1024, "{} nodes searched with a budget of {}", result.stats.nodes, budget);
+        assert!(result.depth >= 1);
+    }
+}
+
+#[test]
+fn a_search_times_the_threads_it_starts() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let threaded = search(BOARD, &SearchConfig {threads: 3, ..SearchConfig::default()}, &heuristic);
+    assert!(threaded.stats.worker_cpu_time > Duration::ZERO);
+    // A search on one thread runs on the caller's, whose own clock times it
+    let single = search(BOARD, &SearchConfig {threads: 1, ..SearchConfig::default()}, &heuristic);
+    assert_eq!(single.stats.worker_cpu_time, Duration::ZERO);
+}
+
+#[test]
+fn a_game_cache_carries_entries_over_until_they_age_out() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    for generations in 0..2 {
+        let config = SearchConfig {prob_threshold: 0.01, cache_generations: generations, ..SearchConfig::default()};
+        let mut cache = SearchCache::new(&config);
+        let first = search_cached(BOARD, &config, &heuristic, &mut cache).stats;
+        let second = search_cached(BOARD, &config, &heuristic, &mut cache).stats;
+        assert_eq!(first.carried_entries, 0);
+        if generations == 0 {
+            assert_eq!((second.carried_entries, second.carried_hits), (0, 0));
+        } else {
+            // The second search finds the first's value for every move straight away
+            assert!(second.carried_entries > 0);
+            assert!(second.carried_hits > 0 && second.carried_hits == second.cache_hits);
+            assert!(second.nodes < first.nodes);
+        }
+    }
+}
+
+#[test]
+fn a_game_cache_reuses_entries_from_earlier_moves_of_a_real_game() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let config = SearchConfig {prob_threshold: 0.01, threads: 1, exact_budgets: false, ..SearchConfig::default()};
+    let mut cache = SearchCache::new(&config);
+    let mut game = Game::with_rules(2, config.rules.clone());
+    let (mut carried_entries, mut carried_hits) = (0, 0);
+    while game.move_count() < 200 && !game.is_over() {
+        let result = search_cached(game.grid().to_bitboard().unwrap(), &config, &heuristic, &mut cache);
+        carried_entries += result.stats.carried_entries;
+        carried_hits += result.stats.carried_hits;
+        game.play(result.best.unwrap()).unwrap();
+    }
+    // The next move's boards have more budget left than the same boards had under the move before, so only
+    // those the earlier search reached by a shorter or likelier way can be reused
+    assert!(carried_entries > 0);
+    assert!(carried_hits > 0, "no carried entries were reused in {} moves", game.move_count());
+}
+
+// Returns the positions of the first moves of a game
+fn game_positions(moves: usize, config: &SearchConfig) -> Vec<Board> {
+    let heuristic = RowHeuristic::default();
+    let mut game = Game::with_rules(2, config.rules.clone());
+    let mut boards = vec!();
+    while boards.len() < moves && !game.is_over() {
+        let board = game.grid().to_bitboard().unwrap();
+        boards.push(board);
+        game.play(find_best_move(board, config, &heuristic).unwrap()).unwrap();
+    }
+    boards
+}
+
+// Checks that searching the first moves of a game with the given settings gives the same values on one thread as
+// on several, with a cache for each move, and with a cache left from other positions. Each visits the nodes in a
+// different order, so reuses different entries.
+fn check_values_do_not_depend_on_visit_order(config: &SearchConfig) {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let single = SearchConfig {threads: 1, ..config.clone()};
+    let boards = game_positions(30, &single);
+    let expected: Vec<_> = boards.iter().map(|&board| search(board, &single, &heuristic).values).collect();
+
+    let threaded = SearchConfig {threads: 4, ..config.clone()};
+    let unshared = SearchConfig {shared_table: false, ..config.clone()};
+    let aged = SearchConfig {cache_generations: 100, ..config.clone()};
+    let mut cache = SearchCache::new(&aged);
+    let mut warmed = vec!([0.0; 4]; boards.len());
+    for (i, &board) in boards.iter().enumerate().rev() {
+        warmed[i] = search_cached(board, &aged, &heuristic, &mut cache).values;
+    }
+
+    for (i, &board) in boards.iter().enumerate() {
+        assert_eq!(search(board, &threaded, &heuristic).values, expected[i], "threaded search of {}", board);
+        assert_eq!(search(board, &unshared, &heuristic).values, expected[i], "unshared search of {}", board);
+        assert_eq!(warmed[i], expected[i], "search of {} with a warm cache", board);
+    }
+}
+
+#[test]
+fn cached_values_do_not_depend_on_the_order_nodes_are_visited() {
+    check_values_do_not_depend_on_visit_order(&SearchConfig {exact_budgets: true, ..SearchConfig::default()});
+}
+
+#[test]
+fn the_default_search_does_not_depend_on_the_order_nodes_are_visited() {
+    check_values_do_not_depend_on_visit_order(&SearchConfig::default());
+}
+
+#[test]
+fn a_search_result_rates_every_move() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let config = SearchConfig {prob_threshold: 0.01, ..SearchConfig::default()};
+    // Only down moves anything on a board whose top row is full and cannot merge
+    let board = Board(0x0000_0000_0000_2121);
+    let result = search(board, &config, &heuristic);
+
+    assert_eq!(result.best, Some(Direction::Down));
+    for &mv in &[Direction::Up, Direction::Left, Direction::Right] {
+        assert_eq!(result.values[mv.index()], 0.0);
+    }
+    assert!(result.values[Direction::Down.index()] > 0.0);
+    assert_eq!(result.depth, config.depth_limit(board));
+    assert!(result.max_depth <= result.depth);
+    assert!(result.stats.nodes > 0);
+}
+
+#[test]
+fn tiles_past_32768_are_searched_exactly() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let config = SearchConfig {prob_threshold: 0.01, ..SearchConfig::default()};
+    // A 65536 beside a 32768 in the top left corner, which a bitboard would show as two 32768s to merge
+    let grid = WideBoard::empty().with_rank(0, 16).with_rank(1, 15).with_rank(4, 1);
+    let result = search_grid(grid, &config, &heuristic);
+
+    assert_eq!(result.values[Direction::Left.index()], 0.0);
+    assert_eq!(result.values[Direction::Up.index()], 0.0);
+    let best = result.best.expect("a move is legal");
+    assert!(grid.legal_moves() & best.mask() != 0);
+}
+
+#[test]
+fn small_and_rectangular_boards_are_searched_like_the_standard_one() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let config = SearchConfig {prob_threshold: 0.1, ..SearchConfig::default()};
+    let mut game = GridGame::<3, 3>::new(1);
+    while let Some(mv) = find_best_grid_move(game.grid(), &config, &heuristic) {
+        game.play(mv).expect("the search only picks legal moves");
+    }
+    assert!(game.is_over());
+    assert!(game.max_rank() >= 6);
+
+    // A 4x5 game is too long to play out, so only its first moves are checked
+    let mut game = GridGame::<4, 5>::new(1);
+    for _ in 0..20 {
+        let result = search_grid(game.grid(), &config, &heuristic);
+        for &mv in &Direction::ALL {
+            if game.legal_moves() & mv.mask() == 0 {
+                assert_eq!(result.values[mv.index()], 0.0);
+            }
+        }
+        game.play(result.best.expect("a move is legal")).unwrap();
+    }
+}
+
+#[test]
+fn a_search_without_spawns_stops_at_the_depth_limit() {
+    init_tables();
+    let heuristic = RowHeuristic::default();
+    let rules = SpawnRules {per_turn: 0, ..SpawnRules::default()};
+    let config = SearchConfig {rules, ..SearchConfig::default()};
+    let result = search(BOARD, &config, &heuristic);
+    assert!(result.best.is_some());
+    assert_eq!(result.max_depth, result.depth);
+}
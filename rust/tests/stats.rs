@@ -0,0 +1,69 @@
+// Checks the summary statistics, confidence intervals and paired tests against known values.
+extern crate twenty48;
+
+use twenty48::stats::*;
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance
+}
+
+#[test]
+fn quantiles_interpolate_between_values() {
+    let values = [3.0, 1.0, 4.0, 1.0, 5.0];
+    assert_eq!(median(&values), 3.0);
+    assert_eq!(quantile(&values, 0.0), 1.0);
+    assert_eq!(quantile(&values, 1.0), 5.0);
+    assert_eq!(quantile(&values, 0.25), 1.0);
+    assert_eq!(quantile(&[1.0, 2.0], 0.5), 1.5);
+}
+
+#[test]
+fn standard_deviation_is_the_sample_one() {
+    let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    assert_eq!(mean(&values), 5.0);
+    assert!(close(std_dev(&values), (32.0f64 / 7.0).sqrt(), 1e-12));
+    assert_eq!(std_dev(&[1.0]), 0.0);
+}
+
+#[test]
+fn t_quantiles_match_tables() {
+    assert!(close(t_quantile(0.975, 1.0), 12.706, 1e-3));
+    assert!(close(t_quantile(0.975, 2.0), 4.303, 1e-3));
+    assert!(close(t_quantile(0.975, 4.0), 2.776, 1e-3));
+    assert!(close(t_quantile(0.975, 30.0), 2.042, 1e-3));
+    assert!(close(t_quantile(0.995, 10.0), 3.169, 1e-3));
+    assert!(close(normal_quantile(0.975), 1.960, 1e-3));
+}
+
+#[test]
+fn mean_interval_uses_t_distribution() {
+    let values = [10.0, 12.0, 14.0, 16.0, 18.0];
+    let (low, high) = mean_interval(&values, 0.95);
+    let margin = 2.776 * std_dev(&values) / 5f64.sqrt();
+    assert!(close(low, 14.0 - margin, 1e-2));
+    assert!(close(high, 14.0 + margin, 1e-2));
+}
+
+#[test]
+fn wilson_intervals_stay_in_range() {
+    let (low, high) = wilson_interval(5, 5, 0.95);
+    assert!(close(low, 0.5655, 1e-3));
+    assert_eq!(high, 1.0);
+    let (low, high) = wilson_interval(0, 10, 0.95);
+    assert_eq!(low, 0.0);
+    assert!(close(high, 0.2775, 1e-3));
+}
+
+#[test]
+fn paired_test_detects_consistent_differences() {
+    let a = [100.0, 200.0, 300.0, 400.0, 500.0, 600.0];
+    let better = [112.0, 209.0, 311.0, 408.0, 513.0, 610.0];
+    let noisy = [150.0, 120.0, 380.0, 350.0, 540.0, 590.0];
+
+    let result = paired_t_test(&a, &better, 0.95);
+    assert!(result.significant);
+    assert!(result.low > 0.0);
+    let result = paired_t_test(&a, &noisy, 0.95);
+    assert!(!result.significant);
+    assert!(result.p_value > 0.05);
+}
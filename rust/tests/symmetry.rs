@@ -0,0 +1,33 @@
+// Checks the rotations and reflections of the board, and its canonical form.
+extern crate twenty48;
+
+use twenty48::{Board, Direction};
+
+// A board with a different rank in every cell, counting up along the rows from the top left, and the last empty
+const BOARD: Board = Board(0x0FED_CBA9_8765_4321);
+
+#[test]
+fn symmetries_move_the_cells_as_their_pictures_show() {
+    assert_eq!(BOARD.mirror(), Board(0xDEF0_9ABC_5678_1234));
+    assert_eq!(BOARD.flip(), Board(0x4321_8765_CBA9_0FED));
+    assert_eq!(BOARD.rotate(), Board(0x48C0_37BF_26AE_159D));
+    assert_eq!(BOARD.rotate().rotate().rotate().rotate(), BOARD);
+    assert_eq!(BOARD.rotate().rotate(), BOARD.mirror().flip());
+}
+
+#[test]
+fn every_rotation_and_reflection_has_the_same_canonical_board() {
+    let canonical = BOARD.canonical();
+    let mut board = BOARD;
+    for _ in 0..4 {
+        for b in [board, board.mirror()] {
+            assert_eq!(b.canonical(), canonical);
+            assert!(canonical.0 <= b.0);
+        }
+        board = board.rotate();
+    }
+
+    // Moving a rotated board is moving the board the other way round
+    let board = Board(0x0001_0021_0032_0143);
+    assert_eq!(board.rotate().make_move(Direction::Right).unwrap(), board.make_move(Direction::Up).unwrap().rotate());
+}